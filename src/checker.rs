@@ -0,0 +1,457 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+
+use crate::config::{Route, Tier};
+use crate::error::{Context, Result};
+use crate::providers::{Failure, Item, Notifier, Source, SourceKind, Tracked};
+use crate::report::{Entry, Report, Section};
+use crate::store::Store;
+
+/// Checks sources and sends the reports to every notifier.
+pub struct Checker {
+    notifiers: Vec<Box<dyn Notifier>>,
+    store: Option<Mutex<Store>>,
+    only_new: bool,
+    routes: Vec<Route>,
+    escalation: Vec<Tier>,
+}
+
+impl Checker {
+    pub fn new(
+        notifiers: Vec<Box<dyn Notifier>>,
+        store: Option<Store>,
+        only_new: bool,
+        routes: Vec<Route>,
+        mut escalation: Vec<Tier>,
+    ) -> Self {
+        escalation.sort_by_key(|tier| tier.after);
+        Checker {
+            notifiers,
+            store: store.map(Mutex::new),
+            only_new,
+            routes,
+            escalation,
+        }
+    }
+
+    /// Returns the last tier the entry reached by its age and report history.
+    fn escalate(&self, entry: &Entry, now: DateTime<Utc>) -> Option<&Tier> {
+        let age = now.signed_duration_since(entry.item.created_at);
+        let reports = entry.record.as_ref().map_or(0, |record| record.times);
+        self.escalation
+            .iter()
+            .rev()
+            .find(|tier| age >= tier.after && reports >= tier.min_reports)
+    }
+
+    pub async fn check(&self, sources: &[&dyn Source]) -> Result<()> {
+        let now = Utc::now();
+        let mut report = Report::new();
+        let mut summary = vec![];
+        let mut failed_sources = 0;
+
+        for source in sources {
+            println!("Checking {}", source.name());
+            let tracked = match &self.store {
+                Some(store) => store.lock().await.tracked(&source.name()),
+                None => Tracked::default(),
+            };
+            // a failing source does not keep the others from being reported
+            let fetched = match source.fetch(&tracked).await {
+                Ok(fetched) => fetched,
+                Err(e) => {
+                    println!("Failed to check {}: {}", source.name(), e);
+                    summary.push(format!("{}: failed, {}", source.name(), e));
+                    report.failures.push(Failure {
+                        target: source.name(),
+                        reason: e.to_string(),
+                    });
+                    failed_sources += 1;
+                    continue;
+                }
+            };
+            let mut items = fetched.items;
+            if fetched.failures.is_empty() {
+                summary.push(format!("{}: {} items", source.name(), items.len()));
+            } else {
+                summary.push(format!(
+                    "{}: {} items, could not check {}",
+                    source.name(),
+                    items.len(),
+                    fetched.failures.len()
+                ));
+            }
+            for item in items.iter_mut() {
+                if let Some(route) = self.routes.iter().find(|route| route_matches(route, item)) {
+                    item.channel = Some(route.channel.to_owned());
+                }
+            }
+            let mut section = Section::new(source.name(), source.describe(), items);
+            if let Some(store) = &self.store {
+                let mut store = store.lock().await;
+                let waiting: HashSet<String> = section
+                    .entries
+                    .iter()
+                    .map(|entry| entry.item.url.to_owned())
+                    .collect();
+                for entry in section.entries.iter_mut() {
+                    entry.record = store.get(&section.source, &entry.item.url).cloned();
+                }
+                if self.only_new {
+                    section.entries.retain(|entry| entry.record.is_none());
+                }
+                // items of what could not be checked are not known to be
+                // resolved, so nothing is until the source is checked fully
+                if fetched.failures.is_empty() {
+                    let mut expired = vec![];
+                    for (url, record) in store.unresolved(&section.source, &waiting) {
+                        // left out unanswered, not resolved
+                        if fetched.skipped.contains(&url) {
+                            expired.push(url);
+                        } else {
+                            section.resolved.push((url, record));
+                        }
+                    }
+                    if !expired.is_empty() {
+                        for url in &expired {
+                            println!("No longer checking {}", url);
+                            store.expire(&section.source, url, now);
+                        }
+                        store.save().context("saving the state file")?;
+                    }
+                }
+            }
+            for entry in section.entries.iter_mut() {
+                if let Some(tier) = self.escalate(entry, now) {
+                    entry.mentions = tier.mention.clone();
+                    if tier.mention_owners {
+                        entry.mentions.extend(entry.item.owners.iter().cloned());
+                    }
+                }
+            }
+            report.push(section);
+            report.failures.extend(fetched.failures);
+        }
+
+        if report.is_empty() {
+            println!("Nothing to report");
+            print_summary(&summary);
+            return failures(failed_sources, 0);
+        }
+
+        let mut failed = 0;
+        for report in report.split_by_channel() {
+            let mut delivered = vec![];
+            for notifier in &self.notifiers {
+                match notifier.send(&report).await {
+                    Ok(delivery) => {
+                        if let Some(destination) = delivery.destination {
+                            summary.push(format!("{}: sent {} items", destination, report.len()));
+                            delivered.push(destination);
+                        }
+                        for failure in delivery.failures {
+                            println!("Failed to notify {}: {}", failure.target, failure.reason);
+                            summary.push(format!("{}: failed, {}", failure.target, failure.reason));
+                            failed += 1;
+                        }
+                    }
+                    Err(e) => {
+                        println!("Failed to notify {}: {}", notifier.name(), e);
+                        summary.push(format!("{}: failed, {}", notifier.name(), e));
+                        failed += 1;
+                    }
+                }
+            }
+            if delivered.is_empty() {
+                continue;
+            }
+
+            if let Some(store) = &self.store {
+                let mut store = store.lock().await;
+                for section in &report.sections {
+                    for entry in &section.entries {
+                        store.reported(&section.source, &entry.item, &delivered, now);
+                    }
+                    for (url, _) in &section.resolved {
+                        store.resolve(&section.source, url, now);
+                    }
+                }
+                store.save().context("saving the state file")?;
+            }
+        }
+
+        print_summary(&summary);
+        failures(failed_sources, failed)
+    }
+}
+
+/// Returns whether the item matches all the set conditions of the route.
+fn route_matches(route: &Route, item: &Item) -> bool {
+    let is_issue = item.kind == SourceKind::GitHub;
+    let is_topic = item.kind == SourceKind::Discourse;
+    if let Some(repo) = &route.repo {
+        if !is_issue || &item.group != repo {
+            return false;
+        }
+    }
+    if let Some(label) = &route.label {
+        let label = label.to_lowercase();
+        if !is_issue || !item.tags.iter().any(|tag| tag.to_lowercase() == label) {
+            return false;
+        }
+    }
+    if let Some(category) = &route.category {
+        if !is_topic || &item.group != category {
+            return false;
+        }
+    }
+    true
+}
+
+/// Fails the run if any source or notification failed.
+fn failures(sources: usize, notifications: usize) -> Result<()> {
+    let mut failed = vec![];
+    if sources != 0 {
+        failed.push(format!("{} source(s)", sources));
+    }
+    if notifications != 0 {
+        failed.push(format!("{} notification(s)", notifications));
+    }
+    if failed.is_empty() {
+        return Ok(());
+    }
+    Err(format!("{} failed", failed.join(" and ")).into())
+}
+
+/// Prints what happened to each source and notifier in a run.
+fn print_summary(summary: &[String]) {
+    println!("Summary:");
+    for line in summary {
+        println!("  {}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use chrono::Duration;
+
+    use super::*;
+    use crate::config::Window;
+    use crate::providers::{new_test_item as new_item, Delivery, Fetched};
+
+    /// Yields its items within 3 days, and the tracked ones of any age.
+    struct FakeSource {
+        name: &'static str,
+        items: Vec<Item>,
+        skipped: Vec<&'static str>,
+    }
+
+    #[async_trait]
+    impl Source for FakeSource {
+        fn kind(&self) -> SourceKind {
+            SourceKind::GitHub
+        }
+
+        fn name(&self) -> String {
+            self.name.to_owned()
+        }
+
+        fn describe(&self) -> String {
+            "no-reply issues".to_owned()
+        }
+
+        async fn fetch(&self, tracked: &Tracked) -> Result<Fetched> {
+            let window = Window {
+                min_age: None,
+                max_age: Some(Duration::days(3)),
+            };
+            Ok(Fetched {
+                items: self
+                    .items
+                    .iter()
+                    .filter(|item| tracked.admits(window, &item.url, item.created_at, Utc::now()))
+                    .cloned()
+                    .collect(),
+                failures: vec![],
+                skipped: self.skipped.iter().map(|url| url.to_string()).collect(),
+            })
+        }
+    }
+
+    /// Keeps the urls of the items and resolved items it was sent, and
+    /// fails to deliver to `failed`.
+    struct FakeNotifier {
+        sent: Arc<std::sync::Mutex<Vec<String>>>,
+        failed: Vec<&'static str>,
+    }
+
+    #[async_trait]
+    impl Notifier for FakeNotifier {
+        fn name(&self) -> String {
+            "fake".to_owned()
+        }
+
+        async fn send(&self, report: &Report) -> Result<Delivery> {
+            let mut sent = self.sent.lock().unwrap();
+            for section in &report.sections {
+                sent.extend(section.entries.iter().map(|e| e.item.url.to_owned()));
+                sent.extend(section.resolved.iter().map(|(url, _)| url.to_owned()));
+            }
+            let mut delivery = Delivery::to("fake".to_owned());
+            delivery.failures = self
+                .failed
+                .iter()
+                .map(|target| Failure {
+                    target: target.to_string(),
+                    reason: "failed".to_owned(),
+                })
+                .collect();
+            Ok(delivery)
+        }
+    }
+
+    fn new_checker(name: &str) -> (Checker, Arc<std::sync::Mutex<Vec<String>>>) {
+        let path = std::env::temp_dir().join(format!("pingbot-checker-{}.json", name));
+        let _ = std::fs::remove_file(&path);
+        let store = Store::open(path.to_string_lossy().into_owned()).unwrap();
+        let sent = Arc::new(std::sync::Mutex::new(vec![]));
+        let notifier = FakeNotifier {
+            sent: sent.clone(),
+            failed: vec![],
+        };
+        let checker = Checker::new(vec![Box::new(notifier)], Some(store), false, vec![], vec![]);
+        (checker, sent)
+    }
+
+    #[tokio::test]
+    async fn resolve_only_answered() {
+        let (checker, sent) = new_checker("resolve");
+        let now = Utc::now();
+        let old = now - Duration::days(5);
+        {
+            let mut store = checker.store.as_ref().unwrap().lock().await;
+            let channels = vec!["fake".to_owned()];
+            store.reported("github", &new_item("answered", now), &channels, now);
+            store.reported("github", &new_item("old", old), &channels, now);
+            store.reported("github", &new_item("labeled", now), &channels, now);
+        }
+        let source = FakeSource {
+            name: "github",
+            items: vec![
+                new_item("new", now),
+                new_item("old", old),
+                new_item("never-seen", old),
+            ],
+            skipped: vec!["labeled"],
+        };
+        checker.check(&[&source]).await.unwrap();
+
+        // the old item is tracked past the window, the untracked one is not
+        assert_eq!(*sent.lock().unwrap(), vec!["new", "old", "answered"]);
+        let store = checker.store.as_ref().unwrap().lock().await;
+        for url in &["answered", "labeled", "never-seen"] {
+            assert!(store.get("github", url).is_none(), "{}", url);
+        }
+        assert_eq!(store.get("github", "old").unwrap().times, 2);
+        let unresolved = store.unresolved("github", &HashSet::new());
+        assert_eq!(unresolved.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn same_url_of_sources() {
+        let (checker, sent) = new_checker("sources");
+        let now = Utc::now();
+        {
+            let mut store = checker.store.as_ref().unwrap().lock().await;
+            let channels = vec!["fake".to_owned()];
+            store.reported("github-pulls", &new_item("pull", now), &channels, now);
+            store.reported("github-reviews", &new_item("pull", now), &channels, now);
+        }
+        // reviewed by a member, but the review of another is still requested
+        let pulls = FakeSource {
+            name: "github-pulls",
+            items: vec![],
+            skipped: vec![],
+        };
+        let reviews = FakeSource {
+            name: "github-reviews",
+            items: vec![new_item("pull", now)],
+            skipped: vec![],
+        };
+        checker.check(&[&pulls, &reviews]).await.unwrap();
+
+        assert_eq!(*sent.lock().unwrap(), vec!["pull", "pull"]);
+        let store = checker.store.as_ref().unwrap().lock().await;
+        assert!(store.get("github-pulls", "pull").is_none());
+        assert_eq!(store.get("github-reviews", "pull").unwrap().times, 2);
+    }
+
+    #[tokio::test]
+    async fn record_partial_delivery() {
+        let (mut checker, sent) = new_checker("partial");
+        checker.notifiers = vec![Box::new(FakeNotifier {
+            sent,
+            failed: vec!["slack U2"],
+        })];
+        let source = FakeSource {
+            name: "github",
+            items: vec![new_item("new", Utc::now())],
+            skipped: vec![],
+        };
+        // the failed part fails the run, but what was delivered is recorded
+        assert!(checker.check(&[&source]).await.is_err());
+        let store = checker.store.as_ref().unwrap().lock().await;
+        assert_eq!(store.get("github", "new").unwrap().channels, vec!["fake"]);
+    }
+
+    #[test]
+    fn route() {
+        let item = |kind, group: &str, tags: Vec<&str>| Item {
+            kind,
+            group: group.to_owned(),
+            tags: tags.into_iter().map(Into::into).collect(),
+            ..new_item("", Utc::now())
+        };
+        let route = Route {
+            repo: Some("o/r".to_owned()),
+            label: Some("Bug".to_owned()),
+            category: None,
+            channel: "c".to_owned(),
+        };
+        assert!(route_matches(
+            &route,
+            &item(SourceKind::GitHub, "o/r", vec!["bug"])
+        ));
+        assert!(!route_matches(
+            &route,
+            &item(SourceKind::GitHub, "o/r", vec!["doc"])
+        ));
+        assert!(!route_matches(
+            &route,
+            &item(SourceKind::GitHub, "o/x", vec!["bug"])
+        ));
+        let route = Route {
+            repo: None,
+            label: None,
+            category: Some("c1".to_owned()),
+            channel: "c".to_owned(),
+        };
+        assert!(route_matches(
+            &route,
+            &item(SourceKind::Discourse, "c1", vec!["c1"])
+        ));
+        assert!(!route_matches(
+            &route,
+            &item(SourceKind::Discourse, "c2", vec!["c2"])
+        ));
+        assert!(!route_matches(
+            &route,
+            &item(SourceKind::GitHub, "c1", vec![])
+        ));
+    }
+}
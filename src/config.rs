@@ -1,23 +1,70 @@
-use std::{fs::read_to_string, io::Error};
+use std::{collections::HashMap, fmt, fs::read_to_string, path::Path};
 
-use serde::Deserialize;
+use chrono::{DateTime, Duration, Utc};
+use serde::{de, Deserialize, Deserializer};
 use toml;
 
+use crate::error::{Context, Error};
+
 #[derive(Deserialize)]
 pub struct Config {
+    #[serde(default)]
     #[serde(rename = "slack-token")]
     pub slack_token: String,
+    #[serde(default)]
     #[serde(rename = "slack-channel")]
     pub slack_channel: String,
+    #[serde(default)]
+    #[serde(rename = "notifiers")]
+    pub notifiers: Vec<NotifierConfig>,
+    /// Handlebars template of an item line in text reports
+    #[serde(rename = "item-template")]
+    pub item_template: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "templates")]
+    pub templates: TemplateFiles,
 
+    /// not needed with `github-app`
+    #[serde(default)]
     #[serde(rename = "github-token")]
     pub github_token: String,
+    /// authenticates as a GitHub App instead of with `github-token` if set
+    #[serde(rename = "github-app")]
+    pub github_app: Option<GitHubApp>,
+    #[serde(default = "default_api_base_url")]
+    #[serde(rename = "github-api-base-url")]
+    pub github_api_base_url: String,
+    #[serde(default = "default_web_base_url")]
+    #[serde(rename = "github-web-base-url")]
+    pub github_web_base_url: String,
+    #[serde(default)]
+    #[serde(rename = "github-backend")]
+    pub github_backend: GitHubBackend,
+    /// responses are cached in this file for conditional requests if set
+    #[serde(default)]
+    #[serde(rename = "github-cache-file")]
+    pub github_cache_file: String,
+    /// "owner/repo", "owner/prefix-*", or "org:owner" for all its repos
     #[serde(default)]
     #[serde(rename = "repos")]
     pub repos: Vec<String>,
+    #[serde(flatten)]
+    pub repo_filter: RepoFilter,
     #[serde(default)]
     #[serde(rename = "filter-labels")]
     pub filter_labels: Vec<String>,
+    /// windows of repos, override the global window
+    #[serde(default)]
+    #[serde(rename = "repo-windows")]
+    pub repo_windows: HashMap<String, Window>,
+    /// repos with their own rules, in addition to `repos`
+    #[serde(default)]
+    #[serde(rename = "repo")]
+    pub repo_rules: Vec<RepoRule>,
+    /// more GitHub instances, like GitHub Enterprise Servers
+    #[serde(default)]
+    #[serde(rename = "github")]
+    pub github_instances: Vec<GitHubInstance>,
 
     #[serde(rename = "discourse-base-url")]
     pub discourse_base_url: String,
@@ -27,14 +74,487 @@ pub struct Config {
     #[serde(default)]
     #[serde(rename = "discourse-members")]
     pub discourse_members: Vec<String>,
+    /// windows of categories, override the global window
+    #[serde(default)]
+    #[serde(rename = "category-windows")]
+    pub category_windows: HashMap<String, Window>,
+
+    /// the global window of item ages
+    #[serde(flatten)]
+    pub window: Window,
+
+    #[serde(default = "default_sources")]
+    #[serde(rename = "sources")]
+    pub sources: Vec<String>,
+    /// requests in flight at once to each host
+    #[serde(default = "default_max_concurrent_requests")]
+    #[serde(rename = "max-concurrent-requests")]
+    pub max_concurrent_requests: usize,
+    /// review requests pending for longer than this are reported by the
+    /// "-reviews" sources
+    #[serde(default = "default_stale_review_after")]
+    #[serde(deserialize_with = "deserialize_duration")]
+    #[serde(rename = "stale-review-after")]
+    pub stale_review_after: Duration,
+    /// reported items are remembered in this file if set
+    #[serde(default)]
+    #[serde(rename = "state-file")]
+    pub state_file: String,
+    #[serde(default)]
+    #[serde(rename = "only-new")]
+    pub only_new: bool,
+
+    /// routes of items to channels, the first matching route applies
+    #[serde(default)]
+    #[serde(rename = "route")]
+    pub routes: Vec<Route>,
+
+    /// escalation tiers of items which stay unanswered
+    #[serde(default)]
+    #[serde(rename = "escalation")]
+    pub escalation: Vec<Tier>,
+
+    /// cron expressions by source, used in daemon mode
+    #[serde(default)]
+    #[serde(rename = "schedules")]
+    pub schedules: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum NotifierConfig {
+    #[serde(rename = "slack")]
+    Slack {
+        /// falls back to `slack-token`
+        token: Option<String>,
+        channel: String,
+        /// send Block Kit messages instead of plain text
+        #[serde(default)]
+        blocks: bool,
+    },
+    /// reminds the requested reviewers of pull requests in direct messages
+    #[serde(rename = "slack-reviewers")]
+    SlackReviewers {
+        /// falls back to `slack-token`
+        token: Option<String>,
+        /// Slack user ids by GitHub login or "org/team"
+        users: HashMap<String, String>,
+    },
+    #[serde(rename = "stdout")]
+    Stdout,
+}
+
+/// Handlebars template files of text reports, the defaults are used for
+/// the ones not set.
+#[derive(Deserialize, Default, Debug, PartialEq)]
+pub struct TemplateFiles {
+    pub report: Option<String>,
+    pub section: Option<String>,
+    pub item: Option<String>,
+}
+
+/// A GitHub instance with its own token, checked as a source of its own.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct GitHubInstance {
+    /// names the source in `sources`, reports, state and schedules
+    pub name: String,
+    #[serde(default = "default_api_base_url")]
+    #[serde(rename = "api-base-url")]
+    pub api_base_url: String,
+    #[serde(default = "default_web_base_url")]
+    #[serde(rename = "web-base-url")]
+    pub web_base_url: String,
+    /// not needed with `app`
+    #[serde(default)]
+    pub token: String,
+    /// authenticates as a GitHub App instead of with `token` if set
+    pub app: Option<GitHubApp>,
+    #[serde(default)]
+    pub repos: Vec<String>,
+    #[serde(flatten)]
+    pub repo_filter: RepoFilter,
+    /// falls back to the global `filter-labels`
+    #[serde(rename = "filter-labels")]
+    pub filter_labels: Option<Vec<String>>,
+    #[serde(default)]
+    pub backend: GitHubBackend,
+    /// responses are cached in this file for conditional requests if set
+    #[serde(default)]
+    #[serde(rename = "cache-file")]
+    pub cache_file: String,
+}
+
+/// Which repos are checked besides the ones listed.
+#[derive(Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct RepoFilter {
+    /// repos or globs which are never checked
+    #[serde(default)]
+    #[serde(rename = "exclude-repos")]
+    pub exclude: Vec<String>,
+    /// check archived repos matched by globs
+    #[serde(default)]
+    #[serde(rename = "include-archived")]
+    pub include_archived: bool,
+    /// check forks matched by globs
+    #[serde(default)]
+    #[serde(rename = "include-forks")]
+    pub include_forks: bool,
+}
+
+/// A GitHub App, which checks the repos of each owner as its installation on
+/// that owner.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct GitHubApp {
+    #[serde(rename = "app-id")]
+    pub app_id: u64,
+    /// PEM file of a private key of the app
+    #[serde(rename = "private-key-file")]
+    pub private_key_file: String,
+}
+
+/// How the GitHub source fetches issues.
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+pub enum GitHubBackend {
+    /// a REST call per page of issues, and per issue for its comments
+    #[default]
+    #[serde(rename = "rest")]
+    Rest,
+    /// issues with their comments in one paginated GraphQL query
+    #[serde(rename = "graphql")]
+    GraphQL,
+}
+
+/// Triage rules of a repo.
+#[derive(Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct RepoRule {
+    pub name: String,
+    /// the GitHub instance of the repo
+    #[serde(default = "default_instance")]
+    pub instance: String,
+    /// falls back to the global `filter-labels`
+    #[serde(rename = "filter-labels")]
+    pub filter_labels: Option<Vec<String>>,
+    /// if set, only issues with any of these labels are reported
+    #[serde(default)]
+    #[serde(rename = "include-labels")]
+    pub include_labels: Vec<String>,
+    #[serde(flatten)]
+    pub window: Window,
+    #[serde(rename = "slack-channel")]
+    pub slack_channel: Option<String>,
+    #[serde(default)]
+    pub owners: Vec<String>,
+}
+
+/// Routes the items matching all the set conditions to a channel.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Route {
+    pub repo: Option<String>,
+    pub label: Option<String>,
+    pub category: Option<String>,
+    pub channel: String,
+}
+
+/// Items are reported only if their age is within the window.
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+pub struct Window {
+    #[serde(default)]
+    #[serde(rename = "min-age")]
+    #[serde(deserialize_with = "deserialize_opt_duration")]
+    pub min_age: Option<Duration>,
+    #[serde(default)]
+    #[serde(rename = "max-age")]
+    #[serde(deserialize_with = "deserialize_opt_duration")]
+    pub max_age: Option<Duration>,
+}
+
+impl Window {
+    /// Returns the window with the ages set in `other` overridden.
+    pub fn merge(&self, other: &Window) -> Window {
+        Window {
+            min_age: other.min_age.or(self.min_age),
+            max_age: other.max_age.or(self.max_age),
+        }
+    }
+
+    pub fn contains(&self, created_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        let age = now.signed_duration_since(created_at);
+        !matches!(self.min_age, Some(min_age) if age < min_age)
+            && !matches!(self.max_age, Some(max_age) if age > max_age)
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min_age, self.max_age) {
+            (None, None) => Ok(()),
+            (None, Some(max_age)) => write!(f, "within {}", format_duration(max_age)),
+            (Some(min_age), None) => write!(f, "older than {}", format_duration(min_age)),
+            (Some(min_age), Some(max_age)) => write!(
+                f,
+                "{} to {} old",
+                format_duration(min_age),
+                format_duration(max_age)
+            ),
+        }
+    }
+}
+
+/// An escalation tier, applied to items older than `after` which have been
+/// reported at least `min-reports` times.
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct Tier {
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub after: Duration,
+    #[serde(default)]
+    #[serde(rename = "min-reports")]
+    pub min_reports: u32,
+    #[serde(default)]
+    pub mention: Vec<String>,
+    /// also mention the owners of the repo
+    #[serde(default)]
+    #[serde(rename = "mention-owners")]
+    pub mention_owners: bool,
+}
+
+/// The longest duration read, about 100 years, so subtracting it from now
+/// cannot overflow.
+const MAX_DURATION_SECONDS: i64 = 100 * 365 * 24 * 60 * 60;
+
+/// Parses human durations like "90m", "36h", "5d" or "1d12h".
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let mut seconds = 0;
+    let mut num = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+        let n: i64 = num
+            .parse()
+            .map_err(|_| format!("invalid duration {:?}", s))?;
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(format!("invalid duration unit {:?} in {:?}", c, s)),
+        };
+        seconds = n
+            .checked_mul(unit)
+            .and_then(|n| n.checked_add(seconds))
+            .filter(|&seconds| seconds <= MAX_DURATION_SECONDS)
+            .ok_or_else(|| format!("duration {:?} is too long, at most 100 years", s))?;
+        num.clear();
+    }
+    if !num.is_empty() || s.trim().is_empty() {
+        return Err(format!("invalid duration {:?}, missing unit", s));
+    }
+    Ok(Duration::seconds(seconds))
+}
+
+/// Formats durations the way `parse_duration` reads them, e.g. "1d12h".
+pub fn format_duration(d: Duration) -> String {
+    let mut seconds = d.num_seconds();
+    if seconds == 0 {
+        return "0s".to_owned();
+    }
+    let mut s = String::new();
+    for (unit, size) in &[('d', 24 * 60 * 60), ('h', 60 * 60), ('m', 60), ('s', 1)] {
+        if seconds >= *size {
+            s.push_str(&format!("{}{}", seconds / size, unit));
+            seconds %= size;
+        }
+    }
+    s
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_duration(&s).map_err(de::Error::custom)
+}
+
+fn deserialize_opt_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_duration(deserializer).map(Some)
+}
+
+fn default_sources() -> Vec<String> {
+    vec!["github".to_owned(), "discourse".to_owned()]
+}
+
+fn default_max_concurrent_requests() -> usize {
+    4
+}
+
+fn default_instance() -> String {
+    "github".to_owned()
+}
+
+fn default_stale_review_after() -> Duration {
+    Duration::days(2)
+}
+
+fn default_api_base_url() -> String {
+    "https://api.github.com".to_owned()
+}
+
+fn default_web_base_url() -> String {
+    "https://github.com".to_owned()
 }
 
 impl Config {
     pub fn new(filename: String) -> Result<Self, Error> {
-        let contents = read_to_string(filename)?;
-        let config: Config = toml::from_str(&contents[..]).unwrap();
+        let contents =
+            read_to_string(&filename).with_context(|| format!("reading config {}", filename))?;
+        let mut config: Config = toml::from_str(&contents[..])
+            .with_context(|| format!("parsing config {}", filename))?;
+        config
+            .validate()
+            .with_context(|| format!("checking config {}", filename))?;
+        config.resolve_paths(
+            Path::new(&filename)
+                .parent()
+                .unwrap_or_else(|| Path::new("")),
+        );
         Ok(config)
     }
+
+    /// Resolves the files named in the config against `dir`, the directory
+    /// of the config file, so they do not depend on the working dir.
+    fn resolve_paths(&mut self, dir: &Path) {
+        let resolve = |path: &mut String| {
+            if !path.is_empty() {
+                *path = dir.join(&path[..]).to_string_lossy().into_owned();
+            }
+        };
+        resolve(&mut self.state_file);
+        resolve(&mut self.github_cache_file);
+        if let Some(app) = &mut self.github_app {
+            resolve(&mut app.private_key_file);
+        }
+        for instance in &mut self.github_instances {
+            resolve(&mut instance.cache_file);
+            if let Some(app) = &mut instance.app {
+                resolve(&mut app.private_key_file);
+            }
+        }
+        let templates = &mut self.templates;
+        for path in templates
+            .report
+            .iter_mut()
+            .chain(templates.section.iter_mut())
+            .chain(templates.item.iter_mut())
+        {
+            resolve(path);
+        }
+    }
+
+    /// Rejects settings which parse but cannot work as meant.
+    fn validate(&self) -> Result<(), Error> {
+        // the window of a `[[repo]]` table would silently win
+        if let Some(rule) = self
+            .repo_rules
+            .iter()
+            .find(|rule| self.repo_windows.contains_key(&rule.name))
+        {
+            return Err(format!(
+                "{} has both a `[[repo]]` table and `repo-windows`, set its window in the table",
+                rule.name
+            )
+            .into());
+        }
+        // sources, state and schedules are told apart by the names of instances
+        let mut names = vec!["github"];
+        for instance in &self.github_instances {
+            if instance.name == "discourse" || names.contains(&&instance.name[..]) {
+                return Err(format!("GitHub instance name {} is taken", instance.name).into());
+            }
+            // these name the pull requests and review requests of instances
+            if instance.name.ends_with("-pulls") || instance.name.ends_with("-reviews") {
+                return Err(format!(
+                    "GitHub instance name {} cannot end with -pulls or -reviews",
+                    instance.name
+                )
+                .into());
+            }
+            names.push(&instance.name);
+        }
+        if let Some(rule) = self
+            .repo_rules
+            .iter()
+            .find(|rule| !names.contains(&&rule.instance[..]))
+        {
+            return Err(format!(
+                "`[[repo]]` {} names an unknown instance {}",
+                rule.name, rule.instance
+            )
+            .into());
+        }
+        // the users of reviewers are looked up case-insensitively
+        for notifier in &self.notifiers {
+            if let NotifierConfig::SlackReviewers { users, .. } = notifier {
+                let mut names: Vec<String> = users.keys().map(|name| name.to_lowercase()).collect();
+                names.sort();
+                if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+                    return Err(format!(
+                        "slack-reviewers maps {} more than once, names are case-insensitive",
+                        pair[0]
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the GitHub instances, the flat `github-*` keys make the one
+    /// named "github".
+    pub fn github_instances(&self) -> Vec<GitHubInstance> {
+        let mut instances = vec![GitHubInstance {
+            name: "github".to_owned(),
+            api_base_url: self.github_api_base_url.to_owned(),
+            web_base_url: self.github_web_base_url.to_owned(),
+            token: self.github_token.to_owned(),
+            app: self.github_app.clone(),
+            repos: self.repos.clone(),
+            repo_filter: self.repo_filter.clone(),
+            filter_labels: None,
+            backend: self.github_backend,
+            cache_file: self.github_cache_file.to_owned(),
+        }];
+        instances.extend(self.github_instances.iter().cloned());
+        instances
+    }
+
+    /// Returns the rules of the repos watched on an instance, its `repos`
+    /// come first unless they also have a `[[repo]]` table.
+    pub fn repo_rules_of(&self, instance: &GitHubInstance) -> Vec<RepoRule> {
+        let tables: Vec<&RepoRule> = self
+            .repo_rules
+            .iter()
+            .filter(|rule| rule.instance == instance.name)
+            .collect();
+        let mut rules: Vec<RepoRule> = instance
+            .repos
+            .iter()
+            .filter(|name| !tables.iter().any(|rule| &rule.name == *name))
+            .map(|name| RepoRule {
+                name: name.to_owned(),
+                instance: instance.name.to_owned(),
+                window: self.repo_windows.get(name).cloned().unwrap_or_default(),
+                ..Default::default()
+            })
+            .collect();
+        rules.extend(tables.into_iter().cloned());
+        rules
+    }
 }
 
 #[cfg(test)]
@@ -51,13 +571,86 @@ mod tests {
         // slack
         assert_eq!(config.slack_token, "slack-token");
         assert_eq!(config.slack_channel, "slack-channel");
+        assert_eq!(
+            config.notifiers,
+            vec!(
+                NotifierConfig::Slack {
+                    token: None,
+                    channel: "slack-channel".to_owned(),
+                    blocks: true,
+                },
+                NotifierConfig::Stdout,
+                NotifierConfig::SlackReviewers {
+                    token: None,
+                    users: vec![
+                        ("you06".to_owned(), "U012AB3CD".to_owned()),
+                        ("you06/reviewers".to_owned(), "U045EF6GH".to_owned()),
+                    ]
+                    .into_iter()
+                    .collect(),
+                },
+            )
+        );
         // github
         assert_eq!(config.github_token, "github-token");
+        assert_eq!(config.github_backend, GitHubBackend::GraphQL);
+        assert_eq!(config.github_cache_file, "pingbot-github-cache.json");
         assert_eq!(config.repos, vec!("you06/pingbot"));
+        assert_eq!(
+            config.repo_filter,
+            RepoFilter {
+                exclude: vec!("you06/pingbot-archive".to_owned()),
+                include_archived: false,
+                include_forks: false,
+            }
+        );
         assert_eq!(
             config.filter_labels,
             vec!("filter-label-1", "filter-label-2")
         );
+        let instances = config.github_instances();
+        let rules = config.repo_rules_of(&instances[0]);
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].name, "you06/pingbot");
+        assert_eq!(rules[0].window.min_age, Some(Duration::hours(12)));
+        assert_eq!(rules[0].filter_labels, None);
+        assert_eq!(rules[1].name, "you06/pingbot-docs");
+        assert_eq!(rules[1].filter_labels, Some(vec!()));
+        assert_eq!(rules[1].include_labels, vec!("question"));
+        assert_eq!(rules[1].window.max_age, Some(Duration::days(5)));
+        assert_eq!(rules[1].slack_channel, Some("docs-channel".to_owned()));
+        assert_eq!(rules[1].owners, vec!("<@docs-owner>"));
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[0].name, "github");
+        assert_eq!(instances[0].api_base_url, "https://api.github.com");
+        assert_eq!(instances[0].web_base_url, "https://github.com");
+        assert_eq!(instances[1].name, "github-enterprise");
+        assert_eq!(
+            instances[1].api_base_url,
+            "https://github.example.com/api/v3"
+        );
+        assert_eq!(instances[1].web_base_url, "https://github.example.com");
+        assert_eq!(instances[0].app, None);
+        assert_eq!(instances[1].token, "");
+        assert_eq!(
+            instances[1].app,
+            Some(GitHubApp {
+                app_id: 12345,
+                private_key_file: "pingbot.private-key.pem".to_owned(),
+            })
+        );
+        assert_eq!(instances[1].backend, GitHubBackend::Rest);
+        let rules = config.repo_rules_of(&instances[1]);
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].name, "infra/deploy");
+        assert_eq!(rules[0].instance, "github-enterprise");
+        assert_eq!(rules[0].owners, vec!("<@infra-owner>"));
+        // routes
+        assert_eq!(config.routes.len(), 2);
+        assert_eq!(config.routes[0].label, Some("component/tikv".to_owned()));
+        assert_eq!(config.routes[0].repo, None);
+        assert_eq!(config.routes[1].category, Some("TiDB 开发者".to_owned()));
+        assert_eq!(config.routes[1].channel, "tug-dev-channel");
         // discourse
         assert_eq!(config.discourse_base_url, "https://asktug.com");
         assert_eq!(
@@ -65,5 +658,154 @@ mod tests {
             vec!("TiDB 用户问答", "TiDB 开发者")
         );
         assert_eq!(config.discourse_members, vec!("you06"));
+        assert_eq!(
+            config.category_windows["TiDB 开发者"].max_age,
+            Some(Duration::days(7))
+        );
+        assert_eq!(
+            config.item_template,
+            Some("{{title}} {{url}} opened {{age}} ago by {{author}}".to_owned())
+        );
+        assert_eq!(
+            config.templates,
+            TemplateFiles {
+                report: Some("templates/report.hbs".to_owned()),
+                section: Some("templates/section.hbs".to_owned()),
+                item: None,
+            }
+        );
+        // window
+        assert_eq!(config.window.min_age, None);
+        assert_eq!(config.window.max_age, Some(Duration::days(5)));
+        assert_eq!(
+            config.repo_windows["you06/pingbot"].min_age,
+            Some(Duration::hours(12))
+        );
+        // sources
+        assert_eq!(config.sources, vec!("github", "discourse"));
+        assert_eq!(config.max_concurrent_requests, 8);
+        assert_eq!(config.stale_review_after, Duration::hours(36));
+        // state
+        assert_eq!(config.state_file, "pingbot-state.json");
+        assert!(!config.only_new);
+        // escalation
+        assert_eq!(config.escalation.len(), 3);
+        assert_eq!(config.escalation[0].after, Duration::hours(24));
+        assert_eq!(config.escalation[0].min_reports, 0);
+        assert_eq!(config.escalation[0].mention, vec!("<!channel>"));
+        assert!(config.escalation[1].mention_owners);
+        assert_eq!(config.escalation[2].min_reports, 2);
+        assert_eq!(config.schedules["github"], "0 0 * * * Mon-Fri");
+        assert_eq!(config.schedules["discourse"], "0 0 9,17 * * *");
+    }
+
+    #[test]
+    fn resolve_paths() {
+        let mut config = new_config().unwrap();
+        config.state_file = "/var/lib/pingbot/state.json".to_owned();
+        config.resolve_paths(Path::new("/etc/pingbot"));
+        assert_eq!(config.state_file, "/var/lib/pingbot/state.json");
+        assert_eq!(
+            config.github_cache_file,
+            "/etc/pingbot/pingbot-github-cache.json"
+        );
+        assert_eq!(
+            config.github_instances[0]
+                .app
+                .as_ref()
+                .unwrap()
+                .private_key_file,
+            "/etc/pingbot/pingbot.private-key.pem"
+        );
+        assert_eq!(
+            config.templates.report.as_deref(),
+            Some("/etc/pingbot/templates/report.hbs")
+        );
+    }
+
+    #[test]
+    fn instance_names() {
+        let mut config = new_config().unwrap();
+        config.github_instances[0].name = "github".to_owned();
+        assert!(config.validate().is_err());
+        let mut config = new_config().unwrap();
+        config.repo_rules[0].instance = "gitlab".to_owned();
+        assert!(config.validate().is_err());
+        for name in &["github-pulls", "enterprise-reviews"] {
+            let mut config = new_config().unwrap();
+            config.github_instances[0].name = name.to_string();
+            config.repo_rules.clear();
+            assert!(config.validate().is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn reviewers_once() {
+        let mut config = new_config().unwrap();
+        config.notifiers.push(NotifierConfig::SlackReviewers {
+            token: None,
+            users: vec![("You06", "U1"), ("you06", "U2")]
+                .into_iter()
+                .map(|(name, user)| (name.to_owned(), user.to_owned()))
+                .collect(),
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn repo_window_once() {
+        let mut config = new_config().unwrap();
+        config.repo_windows.insert(
+            "you06/pingbot-docs".to_owned(),
+            Window {
+                min_age: None,
+                max_age: Some(Duration::days(1)),
+            },
+        );
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("36h"), Ok(Duration::hours(36)));
+        assert_eq!(parse_duration("5d"), Ok(Duration::days(5)));
+        assert_eq!(parse_duration("1d12h"), Ok(Duration::hours(36)));
+        assert_eq!(parse_duration(" 90m "), Ok(Duration::minutes(90)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("36").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("9999999999999d").is_err());
+        assert!(parse_duration("36500d1d").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+        assert_eq!(parse_duration("36500d"), Ok(Duration::days(36500)));
+    }
+
+    #[test]
+    fn format_durations() {
+        assert_eq!(format_duration(Duration::hours(36)), "1d12h");
+        assert_eq!(format_duration(Duration::days(5)), "5d");
+        assert_eq!(format_duration(Duration::minutes(90)), "1h30m");
+        assert_eq!(format_duration(Duration::zero()), "0s");
+    }
+
+    #[test]
+    fn window() {
+        let now = Utc::now();
+        let global = Window {
+            min_age: None,
+            max_age: Some(Duration::days(3)),
+        };
+        let repo = Window {
+            min_age: Some(Duration::hours(12)),
+            max_age: None,
+        };
+        let merged = global.merge(&repo);
+        assert_eq!(merged.to_string(), "12h to 3d old");
+        assert_eq!(global.to_string(), "within 3d");
+        assert!(!merged.contains(now - Duration::hours(1), now));
+        assert!(merged.contains(now - Duration::days(1), now));
+        assert!(!merged.contains(now - Duration::days(4), now));
+        assert!(Window::default().contains(now - Duration::days(365), now));
     }
 }
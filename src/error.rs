@@ -0,0 +1,177 @@
+use std::{fmt, io};
+
+use handlebars::{RenderError, TemplateError};
+
+use crate::providers::api::ApiError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors of pingbot, each keeps the error it was caused by.
+pub enum Error {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Http(reqwest::Error),
+    Json(serde_json::Error),
+    Api(ApiError),
+    Jwt(jsonwebtoken::errors::Error),
+    Template(Box<TemplateError>),
+    Render(RenderError),
+    Other(String),
+    /// what was being done when the error happened, e.g. "checking you06/pingbot"
+    Context {
+        context: String,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    pub fn context<C: Into<String>>(self, context: C) -> Self {
+        Error::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Toml(err) => write!(f, "{}", err),
+            Error::Http(err) => write!(f, "{}", err),
+            Error::Json(err) => write!(f, "{}", err),
+            Error::Api(err) => write!(f, "{}", err),
+            Error::Jwt(err) => write!(f, "{}", err),
+            Error::Template(err) => write!(f, "{}", err),
+            Error::Render(err) => write!(f, "{}", err),
+            Error::Other(reason) => write!(f, "{}", reason),
+            Error::Context { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+// `main` prints the error it returns with `Debug`, which should read the same
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Toml(err) => Some(err),
+            Error::Http(err) => Some(err),
+            Error::Json(err) => Some(err),
+            Error::Api(err) => Some(err),
+            Error::Jwt(err) => Some(err),
+            Error::Template(err) => Some(err.as_ref()),
+            Error::Render(err) => Some(err),
+            Error::Other(_) => None,
+            Error::Context { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Self {
+        Error::Toml(err)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Http(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+impl From<ApiError> for Error {
+    fn from(err: ApiError) -> Self {
+        Error::Api(err)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for Error {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        Error::Jwt(err)
+    }
+}
+
+impl From<TemplateError> for Error {
+    fn from(err: TemplateError) -> Self {
+        Error::Template(Box::new(err))
+    }
+}
+
+impl From<RenderError> for Error {
+    fn from(err: RenderError) -> Self {
+        Error::Render(err)
+    }
+}
+
+impl From<String> for Error {
+    fn from(reason: String) -> Self {
+        Error::Other(reason)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(reason: &str) -> Self {
+        Error::Other(reason.to_owned())
+    }
+}
+
+/// Adds context to the errors of results, like `Error::context`.
+pub trait Context<T> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T>;
+
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, f: F) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for std::result::Result<T, E> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T> {
+        self.map_err(|err| err.into().context(context))
+    }
+
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, f: F) -> Result<T> {
+        self.map_err(|err| err.into().context(f()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_chain() {
+        let res: std::result::Result<(), io::Error> =
+            Err(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        let err = res
+            .context("reading config.toml")
+            .with_context(|| "starting".to_owned())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "starting: reading config.toml: no such file"
+        );
+        // the cause is at the end of the chain
+        let mut cause: &dyn std::error::Error = &err;
+        while let Some(source) = cause.source() {
+            cause = source;
+        }
+        assert!(cause.downcast_ref::<io::Error>().is_some());
+    }
+}
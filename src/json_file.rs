@@ -0,0 +1,25 @@
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Reads a JSON file kept between runs, or the default if there is none yet.
+pub fn load<T: DeserializeOwned + Default>(path: &str) -> Result<T, Error> {
+    if !Path::new(path).exists() {
+        return Ok(T::default());
+    }
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents[..]).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Writes a JSON file kept between runs, to a temporary file first so a
+/// crash never leaves half of it.
+pub fn save<T: Serialize>(path: &str, value: &T) -> Result<(), Error> {
+    let contents = serde_json::to_string_pretty(value)?;
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
@@ -1,11 +1,24 @@
+mod checker;
 mod config;
+mod error;
+mod json_file;
 mod providers;
+mod report;
+mod scheduler;
+mod store;
+mod template;
 
+use std::{str::FromStr, sync::Arc};
+
+use checker::Checker;
 use clap::Clap;
 use config::Config;
-use providers::discourse::Discourse;
-use providers::github::GitHub;
-use providers::slack::Slack;
+use cron::Schedule;
+use error::Context;
+use providers::{slack::Slack, Source};
+use scheduler::Job;
+use store::Store;
+use template::Templates;
 
 #[derive(Clap)]
 #[clap(version = "1.0", author = "you06")]
@@ -14,63 +27,77 @@ struct Opts {
     config: String,
     #[clap(short = "p", long = "ping")]
     ping: Option<String>,
+    /// Keep running and check each source on its schedule
+    #[clap(short = "d", long = "daemon")]
+    daemon: bool,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> error::Result<()> {
     let opts: Opts = Opts::parse();
-    let conf = Config::new(opts.config).unwrap();
+    let conf = Config::new(opts.config)?;
+
+    let templates = Arc::new(Templates::new(
+        &conf.templates,
+        conf.item_template.as_deref(),
+    )?);
 
     if let Some(ping) = opts.ping {
-        let slack_client = Slack::new(conf.slack_token.clone());
-        let _ = slack_client
+        let slack_client = Slack::new(
+            conf.slack_token.clone(),
+            conf.slack_channel.clone(),
+            false,
+            false,
+            templates,
+        );
+        slack_client
             .send_message(conf.slack_channel.clone(), ping)
             .await?;
         return Ok(());
     }
 
-    let mut report = "".to_owned();
-    let (mut has_issue, mut has_topic) = (false, false);
-
-    let github_client = GitHub::new(conf.github_token.to_owned(), conf.filter_labels.clone());
-    let user = github_client.get_user_result().await;
-    println!("Current user: {}", user.unwrap());
-
-    let issues = github_client.get_opened_issues(conf.repos.clone()).await?;
-
-    if issues.len() != 0 {
-        has_issue = true;
-        report.push_str(&format!("{} no-reply issues in 3 days\n", issues.len())[..]);
-        for issue in issues {
-            report.push_str(&format!("{}\n", issue)[..]);
-        }
-    }
-
-    let discourse_client = Discourse::new(
-        conf.discourse_base_url.to_owned(),
-        conf.discourse_members.clone(),
+    let sources = providers::from_config(&conf)?;
+    let notifiers = providers::notifiers_from_config(&conf, templates);
+    let store = if conf.state_file.is_empty() {
+        None
+    } else {
+        Some(
+            Store::open(conf.state_file.to_owned())
+                .with_context(|| format!("opening state file {}", conf.state_file))?,
+        )
+    };
+    let checker = Checker::new(
+        notifiers,
+        store,
+        conf.only_new,
+        conf.routes,
+        conf.escalation,
     );
-    let topics = discourse_client
-        .find_no_reply_topics_by_categories(conf.discourse_categories.clone())
-        .await?;
 
-    if topics.len() != 0 {
-        has_topic = true;
-        report.push_str(&format!("\n\n{} no-reply topics in TUG\n", topics.len())[..]);
-        for topic in topics {
-            report.push_str(&format!("{}\n", topic)[..]);
+    if opts.daemon {
+        let mut jobs = vec![];
+        for source in sources {
+            let name = source.name();
+            let expr = conf
+                .schedules
+                .get(&name)
+                .ok_or_else(|| format!("no schedule for {}", name))?;
+            let schedule = Schedule::from_str(expr)
+                .map_err(|e| format!("invalid schedule for {}: {}", name, e))?;
+            jobs.push(Job {
+                name,
+                schedule,
+                task: Arc::from(source),
+            });
         }
+        let checker = Arc::new(checker);
+        return scheduler::run(jobs, move |source: Arc<dyn Source>| {
+            let checker = checker.clone();
+            async move { checker.check(&[source.as_ref()]).await }
+        })
+        .await;
     }
 
-    if conf.slack_token != "" && conf.slack_channel != "" {
-        if has_issue || has_topic {
-            let slack_client = Slack::new(conf.slack_token.clone());
-            let _ = slack_client
-                .send_message(conf.slack_channel.clone(), report)
-                .await?;
-        }
-    } else {
-        println!("{}", report);
-    }
-    Ok(())
+    let sources: Vec<&dyn Source> = sources.iter().map(AsRef::as_ref).collect();
+    checker.check(&sources).await
 }
@@ -0,0 +1,176 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+/// How much of a response body is kept in errors.
+const SNIPPET_LEN: usize = 200;
+
+/// A response an API did not answer as expected.
+#[derive(Debug)]
+pub enum ApiError {
+    /// 401, or 403 which is not a rate limit
+    Auth {
+        url: String,
+        message: String,
+    },
+    NotFound {
+        url: String,
+        message: String,
+    },
+    RateLimited {
+        url: String,
+        message: String,
+    },
+    /// 5xx
+    Server {
+        url: String,
+        status: StatusCode,
+        message: String,
+    },
+    /// any other unsuccessful status
+    Status {
+        url: String,
+        status: StatusCode,
+        message: String,
+    },
+    /// a successful status with an error in the body, like Slack's `ok: false`
+    Rejected {
+        url: String,
+        message: String,
+    },
+    Decode {
+        url: String,
+        error: serde_json::Error,
+        snippet: String,
+    },
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Auth { url, message } => {
+                write!(f, "authentication failed at {}: {}", url, message)
+            }
+            ApiError::NotFound { url, message } => write!(f, "not found at {}: {}", url, message),
+            ApiError::RateLimited { url, message } => {
+                write!(f, "rate limited at {}: {}", url, message)
+            }
+            ApiError::Server {
+                url,
+                status,
+                message,
+            } => write!(f, "server error {} at {}: {}", status, url, message),
+            ApiError::Status {
+                url,
+                status,
+                message,
+            } => write!(f, "unexpected status {} at {}: {}", status, url, message),
+            ApiError::Rejected { url, message } => {
+                write!(f, "request rejected at {}: {}", url, message)
+            }
+            ApiError::Decode {
+                url,
+                error,
+                snippet,
+            } => write!(
+                f,
+                "failed to decode response of {}: {}, body: {}",
+                url, error, snippet
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Decode { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Turns an unsuccessful status into an error, with a snippet of the body.
+pub fn check(url: &str, status: StatusCode, body: &str) -> Result<(), ApiError> {
+    if status.is_success() {
+        return Ok(());
+    }
+    let url = url.to_owned();
+    let message = snippet(body);
+    Err(match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ApiError::Auth { url, message },
+        StatusCode::NOT_FOUND => ApiError::NotFound { url, message },
+        StatusCode::TOO_MANY_REQUESTS => ApiError::RateLimited { url, message },
+        status if status.is_server_error() => ApiError::Server {
+            url,
+            status,
+            message,
+        },
+        status => ApiError::Status {
+            url,
+            status,
+            message,
+        },
+    })
+}
+
+/// Parses a JSON body, keeping a snippet of it if it does not fit.
+pub fn decode<T: DeserializeOwned>(url: &str, body: &str) -> Result<T, ApiError> {
+    serde_json::from_str(body).map_err(|error| ApiError::Decode {
+        url: url.to_owned(),
+        error,
+        snippet: snippet(body),
+    })
+}
+
+/// Cuts a body to `SNIPPET_LEN` chars on one line.
+pub fn snippet(body: &str) -> String {
+    let line = body.split_whitespace().collect::<Vec<&str>>().join(" ");
+    match line.char_indices().nth(SNIPPET_LEN) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses() {
+        let url = "https://api.github.com/user";
+        let body = r#"{"message": "Bad credentials"}"#;
+        assert!(check(url, StatusCode::OK, body).is_ok());
+        let err = check(url, StatusCode::UNAUTHORIZED, body).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"authentication failed at https://api.github.com/user: {"message": "Bad credentials"}"#
+        );
+        assert!(matches!(
+            check(url, StatusCode::NOT_FOUND, body),
+            Err(ApiError::NotFound { .. })
+        ));
+        assert!(matches!(
+            check(url, StatusCode::BAD_GATEWAY, body),
+            Err(ApiError::Server { .. })
+        ));
+        assert!(matches!(
+            check(url, StatusCode::UNPROCESSABLE_ENTITY, body),
+            Err(ApiError::Status { .. })
+        ));
+    }
+
+    #[test]
+    fn decode_errors() {
+        let err = decode::<Vec<i32>>("u", r#"{"message": "Not Found"}"#).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with(r#", body: {"message": "Not Found"}"#));
+        assert!(std::error::Error::source(&err).is_some());
+
+        let long = format!("<html>\n{}</html>", "é".repeat(300));
+        assert_eq!(snippet(&long).chars().count(), SNIPPET_LEN + 3);
+        assert!(snippet(&long).starts_with("<html> é"));
+    }
+}
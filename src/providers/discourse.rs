@@ -2,52 +2,30 @@ use std::{
     collections::{HashMap, HashSet},
     convert::From,
     fmt,
+    sync::Arc,
 };
 
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use futures::future::join_all;
 use reqwest;
 use serde::{Deserialize, Serialize};
-use serde_json::error::Error as JsonError;
+use tokio::sync::Semaphore;
 
-pub type Result<T> = std::result::Result<T, Error>;
-
-#[derive(Debug)]
-pub struct Error {
-    reason: String,
-}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.reason)
-    }
-}
-
-impl std::error::Error for Error {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        None
-    }
-}
-
-impl From<JsonError> for Error {
-    fn from(err: JsonError) -> Self {
-        Error {
-            reason: err.to_string(),
-        }
-    }
-}
-
-impl From<reqwest::Error> for Error {
-    fn from(err: reqwest::Error) -> Self {
-        Error {
-            reason: err.to_string(),
-        }
-    }
-}
+use super::api;
+use super::{Failure, Fetched, Item, Source, SourceKind, Tracked};
+use crate::config::Window;
+use crate::error::{Context, Result};
 
 pub struct Discourse {
     base_url: String,
+    categories: Vec<String>,
     members: HashSet<String>,
+    window: Window,
+    category_windows: HashMap<String, Window>,
     client: reqwest::Client,
+    /// bounds the requests in flight to this host, shared by all sources on it
+    permits: Arc<Semaphore>,
 }
 
 struct Header {
@@ -95,9 +73,15 @@ pub struct Topic {
     id: i32,
     title: String,
     created_at: DateTime<Utc>,
+    /// the time of the last activity, category lists are sorted by it
+    bumped_at: Option<DateTime<Utc>>,
     posters: Vec<Poster>,
     #[serde(skip_deserializing)]
     base_url: String,
+    #[serde(skip_deserializing)]
+    author: String,
+    #[serde(skip_deserializing)]
+    category: String,
 }
 
 impl fmt::Display for Topic {
@@ -110,9 +94,28 @@ impl fmt::Display for Topic {
     }
 }
 
+impl From<Topic> for Item {
+    fn from(topic: Topic) -> Self {
+        Item {
+            url: format!("{}/t/topic/{}", topic.base_url, topic.id),
+            title: topic.title,
+            author: topic.author,
+            created_at: topic.created_at,
+            kind: SourceKind::Discourse,
+            group: topic.category.to_owned(),
+            tags: vec![topic.category],
+            channel: None,
+            owners: vec![],
+            reviewers: vec![],
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct TopicList {
     pub topics: Vec<Topic>,
+    /// set if there are more pages
+    more_topics_url: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -128,11 +131,29 @@ pub struct Comment {
 }
 
 impl Discourse {
-    pub fn new(base_url: String, members: Vec<String>) -> Self {
+    pub fn new(
+        base_url: String,
+        categories: Vec<String>,
+        members: Vec<String>,
+        window: Window,
+        category_windows: HashMap<String, Window>,
+        permits: Arc<Semaphore>,
+    ) -> Self {
         Discourse {
             base_url,
+            categories,
             members: members.into_iter().collect(),
+            window,
+            category_windows,
             client: reqwest::Client::new(),
+            permits,
+        }
+    }
+
+    fn window_of(&self, category: &str) -> Window {
+        match self.category_windows.get(category) {
+            Some(window) => self.window.merge(window),
+            None => self.window,
         }
     }
 
@@ -148,27 +169,70 @@ impl Discourse {
         for header in headers {
             req = req.header(&header.key[..], &header.value[..]);
         }
-        let res = req.send().await?.text().await?;
-        Ok(res)
+        let _permit = self.permits.acquire().await;
+        let res = req.send().await?;
+        let status = res.status();
+        let body = res.text().await?;
+        api::check(url, status, &body)?;
+        Ok(body)
     }
 
     async fn get_categories(&self) -> Result<Vec<Category>> {
         let url = format!("{}/categories.json", self.base_url);
         let res = self.request(&url[..], vec![]).await?;
-        let c: Categories = serde_json::from_str(&res[..])?;
+        let c: Categories = api::decode(&url, &res)?;
         Ok(c.category_list.categories)
     }
 
-    async fn get_topics_by_cate(&self, cate: &Category) -> Result<CategoryContent> {
-        let url = format!("{}/c/{}.json", self.base_url, cate.id);
-        let res = self.request(&url[..], vec![]).await?;
-        let c: CategoryContent = serde_json::from_str(&res[..])?;
-        Ok(c)
+    /// Lists the topics of a category by their last activity, page by page
+    /// until one is inactive since `cutoff`, so no open topic in the window
+    /// is missed.
+    async fn get_topics_by_cate(
+        &self,
+        cate: &Category,
+        cutoff: Option<DateTime<Utc>>,
+    ) -> Result<CategoryContent> {
+        let mut content = CategoryContent {
+            users: vec![],
+            topic_list: TopicList {
+                topics: vec![],
+                more_topics_url: None,
+            },
+        };
+        for page in 0.. {
+            let url = format!("{}/c/{}.json?page={}", self.base_url, cate.id, page);
+            let res = self.request(&url[..], vec![]).await?;
+            let c: CategoryContent = api::decode(&url, &res)?;
+            // a topic created in the window was active since its creation
+            let bumped_at = c.topic_list.topics.last().and_then(|topic| topic.bumped_at);
+            let inactive = matches!((bumped_at, cutoff), (Some(b), Some(c)) if b < c);
+            let done = inactive
+                || c.topic_list.topics.is_empty()
+                || c.topic_list.more_topics_url.is_none();
+            content.users.extend(c.users);
+            content.topic_list.topics.extend(c.topic_list.topics);
+            if done {
+                break;
+            }
+        }
+        Ok(content)
     }
 
-    async fn find_no_reply_topics_by_category(&self, cate: &Category) -> Result<Vec<Topic>> {
+    async fn find_no_reply_topics_by_category(
+        &self,
+        cate: &Category,
+        tracked: &Tracked,
+    ) -> Result<Vec<Topic>> {
+        let now = Utc::now();
+        let window = self.window_of(&cate.name);
+        let cutoff = tracked.cutoff(&cate.name, window, now);
         let mut no_reply_topics = vec![];
-        let category_content = self.get_topics_by_cate(&cate).await?;
+        let category_content = self.get_topics_by_cate(&cate, cutoff).await?;
+        let usernames: HashMap<i32, String> = category_content
+            .users
+            .iter()
+            .map(|user| (user.id, user.username.to_owned()))
+            .collect();
         let pingcap_user_set: HashMap<i32, bool> = category_content
             .users
             .into_iter()
@@ -182,7 +246,11 @@ impl Discourse {
             })
             .collect();
 
-        'outer: for topic in category_content.topic_list.topics {
+        'outer: for mut topic in category_content.topic_list.topics {
+            let url = format!("{}/t/topic/{}", self.base_url, topic.id);
+            if !tracked.admits(window, &url, topic.created_at, now) {
+                continue;
+            }
             for poster in &topic.posters {
                 match pingcap_user_set.get(&poster.user_id) {
                     Some(&u) => {
@@ -195,33 +263,91 @@ impl Discourse {
                     }
                 }
             }
+            // the first poster is the original poster
+            if let Some(poster) = topic.posters.first() {
+                if let Some(username) = usernames.get(&poster.user_id) {
+                    topic.author = username.to_owned();
+                }
+            }
+            topic.category = cate.name.to_owned();
             no_reply_topics.push(topic);
         }
         Ok(no_reply_topics)
     }
 
+    /// Returns the topics waiting for a reply, with the categories which
+    /// could not be checked.
     pub async fn find_no_reply_topics_by_categories(
         &self,
         categories: Vec<String>,
-    ) -> Result<Vec<Topic>> {
+        tracked: &Tracked,
+    ) -> Result<(Vec<Topic>, Vec<Failure>)> {
         let base_url = self.base_url.to_owned();
         let mut no_reply_topics = vec![];
-        let cate_set: HashSet<String> = categories.into_iter().collect();
-        let categories = self.get_categories().await?;
-        for cate in categories {
-            if cate_set.contains(&cate.name) {
-                println!("Finding no-reply topics in {}", cate);
-                let cate_no_reply_topics = self.find_no_reply_topics_by_category(&cate).await?;
-                no_reply_topics.extend(cate_no_reply_topics);
+        let mut failures = vec![];
+        let mut cate_set: HashSet<String> = categories.into_iter().collect();
+        let categories = self.get_categories().await.context("listing categories")?;
+        let categories: Vec<Category> = categories
+            .into_iter()
+            .filter(|cate| cate_set.remove(&cate.name))
+            .collect();
+        // checked concurrently, the results keep the order of the categories
+        let results = join_all(categories.iter().map(|cate| {
+            println!("Finding no-reply topics in {}", cate);
+            self.find_no_reply_topics_by_category(cate, tracked)
+        }))
+        .await;
+        for (cate, result) in categories.iter().zip(results) {
+            match result {
+                Ok(topics) => no_reply_topics.extend(topics),
+                Err(e) => {
+                    println!("Failed to check category {}: {}", cate.name, e);
+                    failures.push(Failure {
+                        target: format!("category {}", cate.name),
+                        reason: e.to_string(),
+                    });
+                }
             }
         }
-        Ok(no_reply_topics
+        // the rest are not in the category list
+        let mut missing: Vec<String> = cate_set.into_iter().collect();
+        missing.sort();
+        failures.extend(missing.into_iter().map(|name| Failure {
+            target: format!("category {}", name),
+            reason: "not found".to_owned(),
+        }));
+        let topics = no_reply_topics
             .into_iter()
             .map(|mut topic| {
                 topic.base_url = base_url.to_owned();
                 topic
             })
-            .collect())
+            .collect();
+        Ok((topics, failures))
+    }
+}
+
+#[async_trait]
+impl Source for Discourse {
+    fn kind(&self) -> SourceKind {
+        SourceKind::Discourse
+    }
+
+    fn describe(&self) -> String {
+        format!("no-reply topics in TUG {}", self.window)
+            .trim_end()
+            .to_owned()
+    }
+
+    async fn fetch(&self, tracked: &Tracked) -> Result<Fetched> {
+        let (topics, failures) = self
+            .find_no_reply_topics_by_categories(self.categories.clone(), tracked)
+            .await?;
+        Ok(Fetched {
+            items: topics.into_iter().map(Into::into).collect(),
+            failures,
+            skipped: vec![],
+        })
     }
 }
 
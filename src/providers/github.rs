@@ -1,70 +1,108 @@
-use std::{collections::HashSet, convert::From, fmt};
-
-use chrono::{DateTime, Utc};
+mod app;
+mod cache;
+mod graphql;
+mod pulls;
+mod rate_limit;
+mod repos;
+mod reviews;
+
+use std::{
+    collections::HashSet,
+    convert::{From, TryFrom},
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use futures::future::join_all;
 use reqwest;
-use serde::{Deserialize, Serialize};
-use serde_json::error::Error as JsonError;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{sync::Semaphore, time::delay_for};
+
+use super::api::{self, ApiError};
+use super::{Failure, Fetched, Item, Source, SourceKind, Tracked};
+use crate::config::{GitHubBackend, GitHubInstance, RepoFilter, RepoRule, Window};
+use crate::error::{Context, Result};
+use rate_limit::{RateLimits, MAX_RETRIES};
+use repos::RepoPattern;
+
+pub use app::App;
+pub use cache::Cache;
+use cache::Cached;
+pub use pulls::PullRequests;
+pub use reviews::ReviewRequests;
 
-const API_BASE_URL: &str = "https://api.github.com";
 const PER_PAGE: usize = 100;
 
-pub type Result<T> = std::result::Result<T, Error>;
-
-#[derive(Debug)]
-pub struct Error {
-    reason: String,
-}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.reason)
-    }
-}
-
-impl std::error::Error for Error {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        None
-    }
-}
-
-impl From<JsonError> for Error {
-    fn from(err: JsonError) -> Self {
-        Error {
-            reason: err.to_string(),
-        }
-    }
-}
-
-impl From<reqwest::Error> for Error {
-    fn from(err: reqwest::Error) -> Self {
-        Error {
-            reason: err.to_string(),
-        }
-    }
+/// How requests to GitHub are authenticated.
+pub enum Auth {
+    /// a personal access token
+    Token(String),
+    /// shared by the sources of an instance
+    App(Arc<App>),
 }
 
 pub struct GitHub {
-    token: String,
+    name: String,
+    /// e.g. "https://api.github.com" or "https://github.example.com/api/v3"
+    api_base_url: String,
+    /// e.g. "https://github.com", issue links are made from it
+    web_base_url: String,
+    auth: Auth,
     client: reqwest::Client,
+    rules: Vec<RepoRule>,
+    /// the parsed names of `rules`, in the same order
+    patterns: Vec<RepoPattern>,
+    exclusions: Vec<RepoPattern>,
+    repo_filter: RepoFilter,
     filter_labels: HashSet<String>,
+    window: Window,
+    backend: GitHubBackend,
+    rate_limits: Mutex<RateLimits>,
+    /// responses of conditional requests, if caching is enabled
+    cache: Option<Mutex<Cache>>,
+    /// bounds the requests in flight to this host, shared by all sources on it
+    permits: Arc<Semaphore>,
 }
 
+#[derive(Clone)]
 struct Header {
     key: String,
     value: String,
 }
 
+/// A response read in full.
+struct RawResponse {
+    status: reqwest::StatusCode,
+    headers: reqwest::header::HeaderMap,
+    body: String,
+}
+
+/// A page of a response, with the url of the next page from the `Link` header.
+struct Page {
+    body: String,
+    next: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 struct Repo {
     owner: String,
     repo: String,
 }
 
-impl From<String> for Repo {
-    fn from(r: String) -> Self {
-        let parsed = r.split("/").map(Into::into).collect::<Vec<String>>();
-        Repo {
-            owner: parsed[0].to_owned(),
-            repo: parsed[1].to_owned(),
+impl TryFrom<&str> for Repo {
+    type Error = String;
+
+    fn try_from(s: &str) -> std::result::Result<Self, String> {
+        let mut parts = s.trim().split('/');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(owner), Some(repo), None) if !owner.is_empty() && !repo.is_empty() => Ok(Repo {
+                owner: owner.to_owned(),
+                repo: repo.to_owned(),
+            }),
+            _ => Err(format!("invalid repo {:?}, expected \"owner/repo\"", s)),
         }
     }
 }
@@ -103,20 +141,43 @@ pub struct Issue {
     repo: String,
     pull_request: Option<Pull>,
     created_at: DateTime<Utc>,
+    user: User,
     author_association: String,
     labels: Vec<Label>,
+    /// the number of member comments, if fetched along with the issue
+    #[serde(skip_deserializing)]
+    member_comments: Option<usize>,
+    #[serde(skip_deserializing)]
+    web_base_url: String,
 }
 
 impl fmt::Display for Issue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "https://github.com/{}/{}/issues/{}",
-            self.owner, self.repo, self.number
+            "{}/{}/{}/issues/{}",
+            self.web_base_url, self.owner, self.repo, self.number
         )
     }
 }
 
+impl From<Issue> for Item {
+    fn from(issue: Issue) -> Self {
+        Item {
+            url: issue.to_string(),
+            title: issue.title,
+            author: issue.user.login,
+            created_at: issue.created_at,
+            kind: SourceKind::GitHub,
+            group: format!("{}/{}", issue.owner, issue.repo),
+            tags: issue.labels.into_iter().map(|label| label.name).collect(),
+            channel: None,
+            owners: vec![],
+            reviewers: vec![],
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Comment {
     html_url: String,
@@ -124,110 +185,374 @@ pub struct Comment {
 }
 
 impl GitHub {
-    pub fn new(token: String, filter_labels: Vec<String>) -> Self {
-        let mut auth_header = "token ".to_owned();
-        auth_header.push_str(&token);
-        GitHub {
-            token: auth_header,
+    /// Takes the name, base urls, backend and repo filter from `instance`,
+    /// fails if any repo or exclusion is malformed.
+    pub fn new(
+        instance: &GitHubInstance,
+        auth: Auth,
+        rules: Vec<RepoRule>,
+        filter_labels: Vec<String>,
+        window: Window,
+        cache: Option<Cache>,
+        permits: Arc<Semaphore>,
+    ) -> Result<Self> {
+        let parse = |name: &String| RepoPattern::try_from(&name[..]);
+        let patterns = rules
+            .iter()
+            .map(|rule| parse(&rule.name))
+            .collect::<std::result::Result<_, _>>()?;
+        let exclusions = instance
+            .repo_filter
+            .exclude
+            .iter()
+            .map(parse)
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(GitHub {
+            name: instance.name.to_owned(),
+            api_base_url: instance.api_base_url.trim_end_matches('/').to_owned(),
+            web_base_url: instance.web_base_url.trim_end_matches('/').to_owned(),
+            auth,
             client: reqwest::Client::new(),
+            rules,
+            patterns,
+            exclusions,
+            repo_filter: instance.repo_filter.clone(),
             filter_labels: filter_labels
                 .into_iter()
                 .map(|label| label.to_lowercase())
                 .collect(),
-        }
+            window,
+            backend: instance.backend,
+            rate_limits: Mutex::new(RateLimits::default()),
+            cache: cache.map(Mutex::new),
+            permits,
+        })
+    }
+
+    /// Returns the `Authorization` header of requests to the repos of `owner`,
+    /// which picks the installation of an app and is ignored with a token.
+    async fn authorization(&self, owner: &str) -> Result<Header> {
+        let value = match &self.auth {
+            Auth::Token(token) => format!("token {}", token),
+            Auth::App(app) => format!("token {}", self.installation_token(app, owner).await?),
+        };
+        Ok(Header {
+            key: "Authorization".to_owned(),
+            value,
+        })
     }
 
     async fn request(&self, url: &str, headers: Vec<Header>) -> Result<String> {
-        let mut req = self
-            .client
-            .get(url)
-            .header(reqwest::header::USER_AGENT, "pingbot")
-            .header(reqwest::header::AUTHORIZATION, &self.token[..]);
-        for header in headers {
-            req = req.header(&header.key[..], &header.value[..]);
+        Ok(self.request_page(url, headers).await?.body)
+    }
+
+    async fn request_page(&self, url: &str, headers: Vec<Header>) -> Result<Page> {
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.lock().unwrap().get(url).cloned());
+        let res = self
+            .send("core", url, || {
+                let mut req = self
+                    .client
+                    .get(url)
+                    .header(reqwest::header::USER_AGENT, "pingbot");
+                for header in &headers {
+                    req = req.header(&header.key[..], &header.value[..]);
+                }
+                // a 304 does not count against the rate limit
+                if let Some(cached) = &cached {
+                    if let Some(etag) = &cached.etag {
+                        req = req.header(reqwest::header::IF_NONE_MATCH, &etag[..]);
+                    }
+                    if let Some(last_modified) = &cached.last_modified {
+                        req = req.header(reqwest::header::IF_MODIFIED_SINCE, &last_modified[..]);
+                    }
+                }
+                req
+            })
+            .await?;
+        if let (reqwest::StatusCode::NOT_MODIFIED, Some(cached)) = (res.status, &cached) {
+            if let Some(cache) = &self.cache {
+                cache.lock().unwrap().hit();
+            }
+            return Ok(Page {
+                body: cached.body.to_owned(),
+                next: cached.next.to_owned(),
+            });
+        }
+
+        let next = res
+            .headers
+            .get(reqwest::header::LINK)
+            .and_then(|link| link.to_str().ok())
+            .and_then(parse_next_link);
+        if let (true, Some(cache)) = (res.status.is_success(), &self.cache) {
+            let header = |name| {
+                res.headers
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(Into::into)
+            };
+            cache.lock().unwrap().put(
+                url,
+                Cached {
+                    etag: header(reqwest::header::ETAG),
+                    last_modified: header(reqwest::header::LAST_MODIFIED),
+                    body: res.body.to_owned(),
+                    next: next.to_owned(),
+                },
+            );
+        }
+        Ok(Page {
+            body: res.body,
+            next,
+        })
+    }
+
+    /// Sends the request to `url` made by `build`, waiting while the quota
+    /// of `resource` is exhausted and retrying when rate limited.
+    async fn send<F>(&self, resource: &str, url: &str, build: F) -> Result<RawResponse>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let mut retries = 0;
+        loop {
+            let wait = self.rate_limits.lock().unwrap().wait(resource, Utc::now());
+            if let Some(wait) = wait {
+                println!(
+                    "GitHub {} quota exhausted, waiting {}s",
+                    resource,
+                    wait.as_secs()
+                );
+                delay_for(wait).await;
+            }
+
+            let (status, headers, body) = {
+                // released before backing off, so other requests can go
+                let _permit = self.permits.acquire().await;
+                let res = build().send().await?;
+                let status = res.status();
+                let headers = res.headers().clone();
+                (status, headers, res.text().await?)
+            };
+            self.rate_limits.lock().unwrap().update(resource, &headers);
+            match rate_limit::backoff(status, &headers, &body, retries, Utc::now()) {
+                Some(_) if retries == MAX_RETRIES => {
+                    return Err(ApiError::RateLimited {
+                        url: url.to_owned(),
+                        message: api::snippet(&body),
+                    }
+                    .into());
+                }
+                Some(wait) => {
+                    println!("GitHub rate limited, retrying in {}s", wait.as_secs());
+                    delay_for(wait).await;
+                    retries += 1;
+                }
+                None => {
+                    if status != reqwest::StatusCode::NOT_MODIFIED {
+                        api::check(url, status, &body)?;
+                    }
+                    return Ok(RawResponse {
+                        status,
+                        headers,
+                        body,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Prints the requests of this run and the quota left.
+    fn print_quota(&self) {
+        for line in self.rate_limits.lock().unwrap().summary() {
+            println!("GitHub quota {}", line);
+        }
+        if let Some(cache) = &self.cache {
+            println!(
+                "GitHub cache: {} responses not modified",
+                cache.lock().unwrap().hits()
+            );
         }
-        let res = req.send().await?.text().await?;
-        Ok(res)
+    }
+
+    /// Saves the cache. A failure only costs the cached responses, so it is
+    /// logged and the fetched items are still reported.
+    fn save_cache(&self) {
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.lock().unwrap().save() {
+                println!("Failed to save the GitHub cache: {}", e);
+            }
+        }
+    }
+
+    /// Fetches every page of a list by following the `rel="next"` links.
+    async fn request_all<T: DeserializeOwned>(
+        &self,
+        url: String,
+        headers: Vec<Header>,
+    ) -> Result<Vec<T>> {
+        self.request_until(url, headers, |_| false).await
+    }
+
+    /// Fetches pages of a list until the last element of a page matches `stop`.
+    async fn request_until<T, F>(
+        &self,
+        url: String,
+        headers: Vec<Header>,
+        stop: F,
+    ) -> Result<Vec<T>>
+    where
+        T: DeserializeOwned,
+        F: Fn(&T) -> bool,
+    {
+        let mut all = vec![];
+        let mut next = Some(url);
+        while let Some(url) = next {
+            let page = self.request_page(&url[..], headers.clone()).await?;
+            let batch: Vec<T> = api::decode(&url, &page.body)?;
+            next = match batch.last() {
+                Some(last) if stop(last) => None,
+                _ => page.next,
+            };
+            all.extend(batch);
+        }
+        Ok(all)
     }
 
     pub async fn get_user_result(&self) -> Result<String> {
-        let url = format!("{}/user", API_BASE_URL);
-        let res = self.request(&url[..], vec![]).await?;
-        let u: User = serde_json::from_str(&res[..])?;
+        let url = format!("{}/user", self.api_base_url);
+        let res = self
+            .request(&url[..], vec![self.authorization("").await?])
+            .await?;
+        let u: User = api::decode(&url, &res)?;
         Ok(u.login.to_owned())
     }
 
-    pub async fn get_opened_issues(&self, raw: Vec<String>) -> Result<Vec<Issue>> {
+    /// Returns the issues waiting for a reply, the urls of the ones left out
+    /// by label or assignee, and the repos and issues which could not be
+    /// checked.
+    async fn get_opened_issues(
+        &self,
+        repos: Vec<Repo>,
+        tracked: &Tracked,
+    ) -> (Vec<Issue>, Vec<String>, Vec<Failure>) {
         let now = Utc::now();
-        let repos = parse_repos(raw);
-        let mut opened_all = vec![];
-        for repo in repos {
-            println!("process {}/{}", repo.owner, repo.repo);
-            let issues = self.get_opened_issues_by_repo(&repo).await?;
-            opened_all.extend(issues);
-        }
+        let (opened_all, mut failures) = fetch_by_repo(&repos, "issues", |repo| async move {
+            let cutoff = self.cutoff_of_repo(repo, tracked, now);
+            match self.backend {
+                GitHubBackend::Rest => self.get_opened_issues_by_repo(repo, cutoff).await,
+                GitHubBackend::GraphQL => self.query_opened_issues_by_repo(repo, cutoff).await,
+            }
+        })
+        .await;
 
+        let mut skipped = vec![];
         let opened_issues: Vec<Issue> = opened_all
             .into_iter()
             .filter(|issue| {
-                if now.signed_duration_since(issue.created_at).num_hours() > 3 * 24 {
+                let url = issue.to_string();
+                if issue.pull_request.is_some()
+                    || !tracked.admits(self.window_of_issue(issue), &url, issue.created_at, now)
+                {
                     return false;
                 }
-                if self.if_filter_by_label(&issue) {
+                // left out while still waiting, so not taken as resolved
+                if self.if_filter_by_label(issue)
+                    || !self.if_include_by_label(issue)
+                    || issue.assignee.is_some()
+                {
+                    skipped.push(url);
                     return false;
                 }
-                issue.pull_request.is_none() && issue.assignee.is_none() // && !if_member(&issue.author_association)
+                true
             })
             .collect();
 
         let mut no_comment_issue = Vec::<Issue>::new();
-        for issue in opened_issues {
-            let comment_num = self.get_comments_by_issue(&issue).await?;
+        let comment_nums = join_all(opened_issues.iter().map(|issue| async move {
+            match issue.member_comments {
+                Some(num) => Ok(num),
+                None => {
+                    self.get_member_comments(&issue.owner, &issue.repo, issue.number)
+                        .await
+                }
+            }
+        }))
+        .await;
+        for (issue, comment_num) in opened_issues.into_iter().zip(comment_nums) {
+            let comment_num = match comment_num {
+                Ok(num) => num,
+                Err(e) => {
+                    println!("Failed to check comments of {}: {}", issue, e);
+                    failures.push(Failure {
+                        target: issue.to_string(),
+                        reason: e.to_string(),
+                    });
+                    continue;
+                }
+            };
             if comment_num == 0 {
                 no_comment_issue.push(issue);
             }
         }
 
-        Ok(no_comment_issue)
+        (no_comment_issue, skipped, failures)
     }
 
-    async fn get_opened_issues_by_repo(&self, repo: &Repo) -> Result<Vec<Issue>> {
-        let mut all = Vec::<Issue>::new();
-        let mut page = 0;
-
-        while all.len() == page * PER_PAGE {
-            page += 1;
-            let url = format!(
-                "{}/repos/{}/{}/issues?page={}&per_page={}",
-                API_BASE_URL, repo.owner, repo.repo, page, PER_PAGE
-            );
-            let headers = vec![Header {
+    /// Lists the open issues of a repo, newest first until `cutoff` if set.
+    async fn get_opened_issues_by_repo(
+        &self,
+        repo: &Repo,
+        cutoff: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Issue>> {
+        // newest first, so paging can stop at the first issue out of the window
+        let mut url = format!(
+            "{}/repos/{}/{}/issues?state=open&sort=created&direction=desc&per_page={}",
+            self.api_base_url, repo.owner, repo.repo, PER_PAGE
+        );
+        let headers = vec![
+            self.authorization(&repo.owner).await?,
+            Header {
                 key: "Accept".to_owned(),
                 value: "application/vnd.github.machine-man-preview".to_owned(),
-            }];
-            let res = self.request(&url[..], headers).await?;
-            let batch: Vec<Issue> = serde_json::from_str(&res[..])?;
-            all.extend(batch);
-        }
-        println!("all ok");
-
+            },
+        ];
+        let all: Vec<Issue> = match cutoff {
+            Some(cutoff) => {
+                // `since` filters by update time, issues created later are
+                // always updated later, it is truncated to the hour so the
+                // url stays the same for cached responses
+                let since = cutoff - Duration::seconds(cutoff.timestamp() % 3600);
+                url.push_str(&format!(
+                    "&since={}",
+                    since.to_rfc3339_opts(SecondsFormat::Secs, true)
+                ));
+                self.request_until(url, headers, |issue: &Issue| issue.created_at < cutoff)
+                    .await?
+            }
+            None => self.request_all(url, headers).await?,
+        };
         Ok(all
             .into_iter()
             .map(|mut issue| {
                 issue.owner = repo.owner.to_owned();
                 issue.repo = repo.repo.to_owned();
+                issue.web_base_url = self.web_base_url.to_owned();
                 issue
             })
             .collect())
     }
 
-    async fn get_comments_by_issue(&self, issue: &Issue) -> Result<usize> {
+    /// Counts the comments of members on an issue or a pull request.
+    async fn get_member_comments(&self, owner: &str, repo: &str, number: i32) -> Result<usize> {
         let url = format!(
             "{}/repos/{}/{}/issues/{}/comments?per_page={}",
-            API_BASE_URL, issue.owner, issue.repo, issue.number, PER_PAGE
+            self.api_base_url, owner, repo, number, PER_PAGE
         );
-        let res = self.request(&url[..], vec![]).await?;
-        let comments: Vec<Comment> = serde_json::from_str(&res[..])?;
+        let headers = vec![self.authorization(owner).await?];
+        let comments: Vec<Comment> = self.request_all(url, headers).await?;
         let member_comments: Vec<Comment> = comments
             .into_iter()
             .filter(|comment| if_member(&comment.author_association))
@@ -235,19 +560,197 @@ impl GitHub {
         Ok(member_comments.len())
     }
 
+    /// Returns the rule of a repo, a rule naming it wins over the globs.
+    fn rule_of_repo(&self, owner: &str, repo: &str) -> Option<&RepoRule> {
+        let matching = || {
+            self.rules
+                .iter()
+                .zip(&self.patterns)
+                .filter(move |(_, pattern)| pattern.matches(owner, repo))
+        };
+        matching()
+            .find(|(_, pattern)| matches!(pattern, RepoPattern::Repo(_)))
+            .or_else(|| matching().next())
+            .map(|(rule, _)| rule)
+    }
+
+    fn window_of_issue(&self, issue: &Issue) -> Window {
+        self.window_of_repo(&issue.owner, &issue.repo)
+    }
+
+    fn window_of_repo(&self, owner: &str, repo: &str) -> Window {
+        match self.rule_of_repo(owner, repo) {
+            Some(rule) => self.window.merge(&rule.window),
+            None => self.window,
+        }
+    }
+
+    /// Returns when listing the issues or pull requests of a repo can stop.
+    fn cutoff_of_repo(
+        &self,
+        repo: &Repo,
+        tracked: &Tracked,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let window = self.window_of_repo(&repo.owner, &repo.repo);
+        tracked.cutoff(&format!("{}/{}", repo.owner, repo.repo), window, now)
+    }
+
+    /// Describes the items with the window, e.g. "no-reply issues within 3d",
+    /// other instances than "github" are told apart by their names.
+    fn describe_as(&self, items: &str) -> String {
+        let mut description = items.to_owned();
+        if self.name != "github" {
+            description.push_str(&format!(" of {}", self.name));
+        }
+        format!("{} {}", description, self.window)
+            .trim_end()
+            .to_owned()
+    }
+
+    /// Starts counting the requests of a run, and checks the credentials.
+    async fn start_fetch(&self) -> Result<()> {
+        self.rate_limits.lock().unwrap().start_run();
+        match &self.auth {
+            Auth::Token(_) => {
+                let user = self
+                    .get_user_result()
+                    .await
+                    .context("getting the current user")?;
+                println!("Current user: {}", user);
+            }
+            // installations do not have a user
+            Auth::App(app) => {
+                app.start_run().await;
+                println!("Current app: {}", app.id());
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the channel and owners of an item from the rule of its repo.
+    fn with_rule(&self, owner: &str, repo: &str, mut item: Item) -> Item {
+        let rule = self.rule_of_repo(owner, repo).cloned().unwrap_or_default();
+        item.channel = rule.slack_channel;
+        item.owners = rule.owners;
+        item
+    }
+
     fn if_filter_by_label(&self, issue: &Issue) -> bool {
-        for label in &issue.labels {
+        self.if_filter_by_labels(&issue.owner, &issue.repo, &issue.labels)
+    }
+
+    fn if_filter_by_labels(&self, owner: &str, repo: &str, labels: &[Label]) -> bool {
+        let repo_filter_labels = self
+            .rule_of_repo(owner, repo)
+            .and_then(|rule| rule.filter_labels.as_ref());
+        for label in labels {
             let lower_label = label.name.to_lowercase();
-            if self.filter_labels.contains(&lower_label) {
+            let filtered = match repo_filter_labels {
+                Some(labels) => labels.iter().any(|l| l.to_lowercase() == lower_label),
+                None => self.filter_labels.contains(&lower_label),
+            };
+            if filtered {
                 return true;
             }
         }
         false
     }
+
+    fn if_include_by_label(&self, issue: &Issue) -> bool {
+        self.if_include_by_labels(&issue.owner, &issue.repo, &issue.labels)
+    }
+
+    fn if_include_by_labels(&self, owner: &str, repo: &str, labels: &[Label]) -> bool {
+        match self.rule_of_repo(owner, repo) {
+            Some(rule) if !rule.include_labels.is_empty() => labels.iter().any(|label| {
+                rule.include_labels
+                    .iter()
+                    .any(|l| l.to_lowercase() == label.name.to_lowercase())
+            }),
+            _ => true,
+        }
+    }
+}
+
+#[async_trait]
+impl Source for GitHub {
+    fn kind(&self) -> SourceKind {
+        SourceKind::GitHub
+    }
+
+    fn name(&self) -> String {
+        self.name.to_owned()
+    }
+
+    fn describe(&self) -> String {
+        self.describe_as("no-reply issues")
+    }
+
+    async fn fetch(&self, tracked: &Tracked) -> Result<Fetched> {
+        self.start_fetch().await?;
+        let (repos, mut failures) = self.expand_repos().await;
+        let (issues, skipped, issue_failures) = self.get_opened_issues(repos, tracked).await;
+        failures.extend(issue_failures);
+        self.print_quota();
+        self.save_cache();
+        let items = issues
+            .into_iter()
+            .map(|issue| {
+                let (owner, repo) = (issue.owner.to_owned(), issue.repo.to_owned());
+                self.with_rule(&owner, &repo, issue.into())
+            })
+            .collect();
+        Ok(Fetched {
+            items,
+            failures,
+            skipped,
+        })
+    }
+}
+
+/// Finds the url of the next page in a `Link` header like
+/// `<https://api.github.com/...&page=2>; rel="next", <...>; rel="last"`.
+fn parse_next_link(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let mut fields = part.split(';');
+        let url = fields.next()?.trim();
+        if fields.any(|field| field.trim() == "rel=\"next\"") {
+            Some(url.trim_start_matches('<').trim_end_matches('>').to_owned())
+        } else {
+            None
+        }
+    })
 }
 
-fn parse_repos(raw: Vec<String>) -> Vec<Repo> {
-    raw.into_iter().map(Into::into).collect()
+/// Checks the repos concurrently with `f`, and returns what they yielded in
+/// the order of the repos, with the repos which could not be checked.
+async fn fetch_by_repo<'a, T, F, R>(repos: &'a [Repo], what: &str, f: F) -> (Vec<T>, Vec<Failure>)
+where
+    F: Fn(&'a Repo) -> R,
+    R: Future<Output = Result<Vec<T>>>,
+{
+    let mut fetched = vec![];
+    let mut failures = vec![];
+    let results = join_all(repos.iter().map(|repo| {
+        println!("process {} of {}/{}", what, repo.owner, repo.repo);
+        f(repo)
+    }))
+    .await;
+    for (repo, result) in repos.iter().zip(results) {
+        match result {
+            Ok(items) => fetched.extend(items),
+            Err(e) => {
+                let target = format!("{}/{}", repo.owner, repo.repo);
+                println!("Failed to check {}: {}", target, e);
+                failures.push(Failure {
+                    target,
+                    reason: e.to_string(),
+                });
+            }
+        }
+    }
+    (fetched, failures)
 }
 
 fn if_member(relation: &String) -> bool {
@@ -263,7 +766,31 @@ mod tests {
 
     fn new_client() -> GitHub {
         let filter_labels = vec!["l1".to_owned(), "l2".to_owned()];
-        GitHub::new("".to_owned(), filter_labels)
+        GitHub::new(
+            &new_instance(),
+            Auth::Token("".to_owned()),
+            vec![],
+            filter_labels,
+            Window::default(),
+            None,
+            Arc::new(Semaphore::new(4)),
+        )
+        .unwrap()
+    }
+
+    fn new_instance() -> GitHubInstance {
+        GitHubInstance {
+            name: "github".to_owned(),
+            api_base_url: "https://api.github.com".to_owned(),
+            web_base_url: "https://github.com".to_owned(),
+            token: "".to_owned(),
+            app: None,
+            repos: vec![],
+            repo_filter: RepoFilter::default(),
+            filter_labels: None,
+            backend: GitHubBackend::Rest,
+            cache_file: "".to_owned(),
+        }
     }
 
     fn new_issue_with_labels(labels: Vec<String>) -> Issue {
@@ -275,6 +802,9 @@ mod tests {
             repo: "".to_owned(),
             pull_request: None,
             created_at: Utc::now(),
+            user: User {
+                login: "".to_owned(),
+            },
             author_association: "".to_owned(),
             labels: labels
                 .into_iter()
@@ -284,6 +814,8 @@ mod tests {
                     description: Some("".to_owned()),
                 })
                 .collect(),
+            member_comments: None,
+            web_base_url: "https://github.com".to_owned(),
         }
     }
 
@@ -297,4 +829,128 @@ mod tests {
         assert_eq!(client.if_filter_by_label(&issue2), true);
         assert_eq!(client.if_filter_by_label(&issue3), false);
     }
+
+    #[test]
+    fn repo_rules() {
+        let rule = RepoRule {
+            name: "o/r".to_owned(),
+            filter_labels: Some(vec!["L3".to_owned()]),
+            include_labels: vec!["l2".to_owned()],
+            ..Default::default()
+        };
+        let client = GitHub::new(
+            &new_instance(),
+            Auth::Token("".to_owned()),
+            vec![rule],
+            vec!["l1".to_owned()],
+            Window::default(),
+            None,
+            Arc::new(Semaphore::new(4)),
+        )
+        .unwrap();
+        let in_repo = |labels: Vec<String>| {
+            let mut issue = new_issue_with_labels(labels);
+            issue.owner = "o".to_owned();
+            issue.repo = "r".to_owned();
+            issue
+        };
+        let issue1 = in_repo(vec!["l1".to_owned(), "l2".to_owned()]);
+        let issue2 = in_repo(vec!["l2".to_owned(), "l3".to_owned()]);
+        let issue3 = in_repo(vec!["l4".to_owned()]);
+        assert!(!client.if_filter_by_label(&issue1));
+        assert!(client.if_filter_by_label(&issue2));
+        assert!(client.if_include_by_label(&issue1));
+        assert!(!client.if_include_by_label(&issue3));
+        // the global rules apply to other repos
+        let issue4 = new_issue_with_labels(vec!["l1".to_owned()]);
+        assert!(client.if_filter_by_label(&issue4));
+        assert!(client.if_include_by_label(&issue4));
+    }
+
+    #[test]
+    fn instances() {
+        let instance = GitHubInstance {
+            name: "ghe".to_owned(),
+            api_base_url: "https://github.example.com/api/v3/".to_owned(),
+            web_base_url: "https://github.example.com/".to_owned(),
+            ..new_instance()
+        };
+        let client = GitHub::new(
+            &instance,
+            Auth::Token("".to_owned()),
+            vec![],
+            vec![],
+            Window::default(),
+            None,
+            Arc::new(Semaphore::new(4)),
+        )
+        .unwrap();
+        assert_eq!(client.name(), "ghe");
+        assert_eq!(client.describe(), "no-reply issues of ghe");
+        assert_eq!(client.api_base_url, "https://github.example.com/api/v3");
+        let mut issue = new_issue_with_labels(vec![]);
+        issue.owner = "o".to_owned();
+        issue.repo = "r".to_owned();
+        issue.web_base_url = client.web_base_url.to_owned();
+        assert_eq!(issue.to_string(), "https://github.example.com/o/r/issues/0");
+        assert_eq!(new_client().describe(), "no-reply issues");
+    }
+
+    #[test]
+    fn glob_rules() {
+        let rule = |name: &str, max_age| RepoRule {
+            name: name.to_owned(),
+            window: Window {
+                min_age: None,
+                max_age: Some(Duration::days(max_age)),
+            },
+            ..Default::default()
+        };
+        let client = GitHub::new(
+            &new_instance(),
+            Auth::Token("".to_owned()),
+            vec![rule("o/*", 1), rule("o/r", 2)],
+            vec![],
+            Window::default(),
+            None,
+            Arc::new(Semaphore::new(4)),
+        )
+        .unwrap();
+        let max_age = |owner, repo| client.window_of_repo(owner, repo).max_age;
+        assert_eq!(max_age("o", "r"), Some(Duration::days(2)));
+        assert_eq!(max_age("O", "x"), Some(Duration::days(1)));
+        assert_eq!(max_age("p", "r"), None);
+
+        let instance = GitHubInstance {
+            repo_filter: RepoFilter {
+                exclude: vec!["o/".to_owned()],
+                ..Default::default()
+            },
+            ..new_instance()
+        };
+        let invalid = GitHub::new(
+            &instance,
+            Auth::Token("".to_owned()),
+            vec![],
+            vec![],
+            Window::default(),
+            None,
+            Arc::new(Semaphore::new(4)),
+        );
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn next_link() {
+        let link = "<https://api.github.com/repositories/1/issues?page=2>; rel=\"next\", \
+                    <https://api.github.com/repositories/1/issues?page=5>; rel=\"last\"";
+        assert_eq!(
+            parse_next_link(link),
+            Some("https://api.github.com/repositories/1/issues?page=2".to_owned())
+        );
+        let link = "<https://api.github.com/repositories/1/issues?page=4>; rel=\"prev\", \
+                    <https://api.github.com/repositories/1/issues?page=1>; rel=\"first\"";
+        assert_eq!(parse_next_link(link), None);
+        assert_eq!(parse_next_link(""), None);
+    }
 }
@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, EncodingKey};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use super::{
+    api::{self, ApiError},
+    GitHub, Header, PER_PAGE,
+};
+use crate::error::Result;
+
+/// Access tokens are replaced when they expire within this many minutes, so
+/// none expires halfway through a run.
+const REFRESH_MINUTES: i64 = 5;
+
+/// Installation APIs were a preview when this was written.
+const ACCEPT: &str = "application/vnd.github.machine-man-preview+json";
+
+/// A GitHub App, which authenticates with a JWT signed by its private key,
+/// then as its installation on the owner of each repo.
+pub struct App {
+    id: u64,
+    key: EncodingKey,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// installation ids by the lowercase login of their accounts
+    installations: HashMap<String, u64>,
+    /// lowercase owners the app was not installed on when last listed this
+    /// run, so their repos do not list the installations again
+    missing: HashSet<String>,
+    /// access tokens by installation id
+    tokens: HashMap<u64, AccessToken>,
+}
+
+#[derive(Serialize)]
+struct Claims {
+    iat: i64,
+    exp: i64,
+    iss: u64,
+}
+
+#[derive(Deserialize)]
+struct Installation {
+    id: u64,
+    account: Account,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct AccessToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl AccessToken {
+    fn is_fresh(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at - now > Duration::minutes(REFRESH_MINUTES)
+    }
+}
+
+impl App {
+    pub fn new(id: u64, private_key: &[u8]) -> Result<Self> {
+        Ok(App {
+            id,
+            key: EncodingKey::from_rsa_pem(private_key)?,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Forgets the owners without an installation, the app may have been
+    /// installed on them since the last run.
+    pub async fn start_run(&self) {
+        self.state.lock().await.missing.clear();
+    }
+
+    /// Signs a JWT for the app, valid for the ten minutes GitHub allows at
+    /// most, issued a minute early in case of clock drift.
+    fn jwt(&self, now: DateTime<Utc>) -> Result<String> {
+        let claims = Claims {
+            iat: (now - Duration::minutes(1)).timestamp(),
+            exp: (now + Duration::minutes(9)).timestamp(),
+            iss: self.id,
+        };
+        let header = jsonwebtoken::Header::new(Algorithm::RS256);
+        Ok(jsonwebtoken::encode(&header, &claims, &self.key)?)
+    }
+
+    /// The headers of requests made as the app itself.
+    fn headers(&self) -> Result<Vec<Header>> {
+        Ok(vec![
+            Header {
+                key: "Authorization".to_owned(),
+                value: format!("Bearer {}", self.jwt(Utc::now())?),
+            },
+            Header {
+                key: "Accept".to_owned(),
+                value: ACCEPT.to_owned(),
+            },
+        ])
+    }
+}
+
+impl GitHub {
+    /// Returns an access token of the installation of `app` on `owner`, a new
+    /// one if there is none yet or it is about to expire.
+    pub(super) async fn installation_token(&self, app: &App, owner: &str) -> Result<String> {
+        // held while requesting, so concurrent requests share one token
+        let mut state = app.state.lock().await;
+        let owner = owner.to_lowercase();
+        if !state.installations.contains_key(&owner) && !state.missing.contains(&owner) {
+            // the app may have been installed on it since the last lookup
+            state.installations = self.get_installations(app).await?;
+            if !state.installations.contains_key(&owner) {
+                state.missing.insert(owner.to_owned());
+            }
+        }
+        let id = *state
+            .installations
+            .get(&owner)
+            .ok_or_else(|| ApiError::NotFound {
+                url: format!("{}/app/installations", self.api_base_url),
+                message: format!("the app is not installed on {}", owner),
+            })?;
+        if let Some(token) = state.tokens.get(&id) {
+            if token.is_fresh(Utc::now()) {
+                return Ok(token.token.to_owned());
+            }
+        }
+        println!("Requesting an access token of installation {}", id);
+        let token = self.create_access_token(app, id).await?;
+        let value = token.token.to_owned();
+        state.tokens.insert(id, token);
+        Ok(value)
+    }
+
+    async fn get_installations(&self, app: &App) -> Result<HashMap<String, u64>> {
+        let url = format!(
+            "{}/app/installations?per_page={}",
+            self.api_base_url, PER_PAGE
+        );
+        let installations: Vec<Installation> = self.request_all(url, app.headers()?).await?;
+        Ok(installations
+            .into_iter()
+            .map(|installation| (installation.account.login.to_lowercase(), installation.id))
+            .collect())
+    }
+
+    async fn create_access_token(&self, app: &App, id: u64) -> Result<AccessToken> {
+        let url = format!(
+            "{}/app/installations/{}/access_tokens",
+            self.api_base_url, id
+        );
+        let headers = app.headers()?;
+        let res = self
+            .send("core", &url, || {
+                let mut req = self
+                    .client
+                    .post(&url[..])
+                    .header(reqwest::header::USER_AGENT, "pingbot");
+                for header in &headers {
+                    req = req.header(&header.key[..], &header.value[..]);
+                }
+                req
+            })
+            .await?;
+        Ok(api::decode(&url, &res.body)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_tokens() {
+        let now = Utc::now();
+        let token = |minutes| AccessToken {
+            token: "t".to_owned(),
+            expires_at: now + Duration::minutes(minutes),
+        };
+        assert!(token(60).is_fresh(now));
+        assert!(!token(4).is_fresh(now));
+        assert!(!token(-1).is_fresh(now));
+        assert!(App::new(1, b"not a key").is_err());
+    }
+}
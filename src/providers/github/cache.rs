@@ -0,0 +1,109 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Error,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::json_file;
+
+/// A response with its validators, reused when GitHub answers
+/// `304 Not Modified` to a conditional request.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Cached {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+    /// the url of the next page
+    pub next: Option<String>,
+}
+
+/// Responses keyed by url, persisted as a JSON file between runs.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Cache {
+    #[serde(skip)]
+    path: String,
+    entries: HashMap<String, Cached>,
+    /// urls requested since the last save, the others are dropped on save
+    #[serde(skip)]
+    used: HashSet<String>,
+    /// responses reused since the last save
+    #[serde(skip)]
+    hits: usize,
+}
+
+impl Cache {
+    pub fn open(path: String) -> Result<Self, Error> {
+        let mut cache: Cache = json_file::load(&path)?;
+        cache.path = path;
+        Ok(cache)
+    }
+
+    /// Drops the responses which were not requested since the last save, so
+    /// pages of closed issues do not pile up, and writes the rest.
+    pub fn save(&mut self) -> Result<(), Error> {
+        let used = std::mem::take(&mut self.used);
+        self.entries.retain(|url, _| used.contains(url));
+        self.hits = 0;
+        json_file::save(&self.path, self)
+    }
+
+    pub fn get(&mut self, url: &str) -> Option<&Cached> {
+        self.used.insert(url.to_owned());
+        self.entries.get(url)
+    }
+
+    /// Remembers a response, it is not worth it without any validator.
+    pub fn put(&mut self, url: &str, cached: Cached) {
+        self.used.insert(url.to_owned());
+        if cached.etag.is_some() || cached.last_modified.is_some() {
+            self.entries.insert(url.to_owned(), cached);
+        } else {
+            self.entries.remove(url);
+        }
+    }
+
+    /// Counts a response answered from the cache.
+    pub fn hit(&mut self) {
+        self.hits += 1;
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_entries() {
+        let path = std::env::temp_dir()
+            .join(format!("pingbot-cache-{}.json", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let cached = |etag: Option<&str>| Cached {
+            etag: etag.map(Into::into),
+            last_modified: None,
+            body: "[]".to_owned(),
+            next: None,
+        };
+        let mut cache = Cache::open(path.to_owned()).unwrap();
+        cache.put("u1", cached(Some("\"e1\"")));
+        cache.put("u2", cached(Some("\"e2\"")));
+        cache.put("u3", cached(None));
+        assert!(cache.get("u3").is_none());
+        cache.save().unwrap();
+
+        let mut cache = Cache::open(path.to_owned()).unwrap();
+        assert_eq!(cache.get("u1"), Some(&cached(Some("\"e1\""))));
+        // u2 is not requested in this run
+        cache.save().unwrap();
+        let mut cache = Cache::open(path.to_owned()).unwrap();
+        assert!(cache.get("u1").is_some());
+        assert!(cache.get("u2").is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
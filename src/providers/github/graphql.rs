@@ -0,0 +1,331 @@
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    api::{self, ApiError},
+    if_member, Assignee, GitHub, Issue, Label, Repo, User, PER_PAGE,
+};
+use crate::error::Result;
+
+/// Open issues of a repo, newest first, with what decides whether they are
+/// waiting for a reply. Only the first 100 comments of an issue are fetched.
+const ISSUES_QUERY: &str = "
+query($owner: String!, $name: String!, $first: Int!, $cursor: String) {
+  repository(owner: $owner, name: $name) {
+    issues(states: OPEN, first: $first, after: $cursor,
+           orderBy: {field: CREATED_AT, direction: DESC}) {
+      pageInfo { hasNextPage endCursor }
+      nodes {
+        number
+        title
+        createdAt
+        author { login }
+        authorAssociation
+        labels(first: 100) { nodes { name description } }
+        assignees(first: 1) { nodes { databaseId login } }
+        comments(first: 100) { totalCount nodes { authorAssociation } }
+      }
+    }
+  }
+}";
+
+#[derive(Serialize)]
+struct Query<'a, V> {
+    query: &'a str,
+    variables: V,
+}
+
+#[derive(Serialize)]
+struct IssuesVariables<'a> {
+    owner: &'a str,
+    name: &'a str,
+    first: usize,
+    cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<QueryError>,
+}
+
+#[derive(Deserialize)]
+struct QueryError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct IssuesData {
+    repository: Option<RepositoryNode>,
+}
+
+#[derive(Deserialize)]
+struct RepositoryNode {
+    issues: IssueConnection,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IssueConnection {
+    page_info: PageInfo,
+    nodes: Vec<IssueNode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Nodes<T> {
+    nodes: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IssueNode {
+    number: i32,
+    title: String,
+    created_at: DateTime<Utc>,
+    /// missing for deleted users
+    author: Option<UserNode>,
+    author_association: String,
+    labels: Nodes<LabelNode>,
+    assignees: Nodes<UserNode>,
+    comments: CommentConnection,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserNode {
+    database_id: Option<i64>,
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct LabelNode {
+    name: String,
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CommentConnection {
+    total_count: usize,
+    nodes: Vec<CommentNode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CommentNode {
+    author_association: String,
+}
+
+impl IssueNode {
+    fn into_issue(self, repo: &Repo, web_base_url: &str) -> Issue {
+        let member_comments = self
+            .comments
+            .nodes
+            .iter()
+            .filter(|comment| if_member(&comment.author_association))
+            .count();
+        Issue {
+            number: self.number,
+            title: self.title,
+            assignee: self
+                .assignees
+                .nodes
+                .into_iter()
+                .next()
+                .map(|user| Assignee {
+                    id: user.database_id.unwrap_or_default(),
+                    login: user.login,
+                }),
+            owner: repo.owner.to_owned(),
+            repo: repo.repo.to_owned(),
+            // pull requests are not issues in GraphQL
+            pull_request: None,
+            created_at: self.created_at,
+            user: User {
+                login: self
+                    .author
+                    .map_or_else(|| "ghost".to_owned(), |user| user.login),
+            },
+            author_association: self.author_association,
+            labels: self
+                .labels
+                .nodes
+                .into_iter()
+                .map(|label| Label {
+                    // GraphQL only has node ids, which are not used
+                    id: 0,
+                    name: label.name,
+                    description: label.description,
+                })
+                .collect(),
+            // the rest of the comments are fetched by REST if none of the
+            // first ones is from a member
+            member_comments: if member_comments > 0
+                || self.comments.total_count <= self.comments.nodes.len()
+            {
+                Some(member_comments)
+            } else {
+                None
+            },
+            web_base_url: web_base_url.to_owned(),
+        }
+    }
+}
+
+/// The GraphQL endpoint of a REST base url, which is `/api/graphql` instead
+/// of `/api/v3` on GitHub Enterprise Server.
+fn graphql_url(api_base_url: &str) -> String {
+    match api_base_url.strip_suffix("/v3") {
+        Some(api) => format!("{}/graphql", api),
+        None => format!("{}/graphql", api_base_url),
+    }
+}
+
+impl GitHub {
+    /// Runs a query on the repos of `owner`.
+    async fn query<V: Serialize, T: DeserializeOwned>(
+        &self,
+        owner: &str,
+        query: &str,
+        variables: V,
+    ) -> Result<T> {
+        let url = graphql_url(&self.api_base_url);
+        let authorization = self.authorization(owner).await?;
+        let body = self
+            .send("graphql", &url, || {
+                self.client
+                    .post(&url[..])
+                    .header(reqwest::header::USER_AGENT, "pingbot")
+                    .header(reqwest::header::AUTHORIZATION, &authorization.value[..])
+                    .json(&Query {
+                        query,
+                        variables: &variables,
+                    })
+            })
+            .await?
+            .body;
+        let res: Response<T> = api::decode(&url, &body)?;
+        if !res.errors.is_empty() {
+            let messages: Vec<String> = res.errors.into_iter().map(|e| e.message).collect();
+            return Err(ApiError::Rejected {
+                url,
+                message: messages.join("; "),
+            }
+            .into());
+        }
+        res.data.ok_or_else(|| {
+            ApiError::Rejected {
+                url,
+                message: format!("no data in {}", api::snippet(&body)),
+            }
+            .into()
+        })
+    }
+
+    /// Fetches the open issues of a repo with their comments, newest first,
+    /// until an issue is older than `cutoff` if set.
+    pub(super) async fn query_opened_issues_by_repo(
+        &self,
+        repo: &Repo,
+        cutoff: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Issue>> {
+        let mut all = vec![];
+        let mut cursor = None;
+        loop {
+            let variables = IssuesVariables {
+                owner: &repo.owner,
+                name: &repo.repo,
+                first: PER_PAGE,
+                cursor,
+            };
+            let data: IssuesData = self.query(&repo.owner, ISSUES_QUERY, variables).await?;
+            let issues = data
+                .repository
+                .ok_or_else(|| ApiError::NotFound {
+                    url: graphql_url(&self.api_base_url),
+                    message: format!("repo {}/{}", repo.owner, repo.repo),
+                })?
+                .issues;
+            let done = match (issues.nodes.last(), cutoff) {
+                (Some(last), Some(cutoff)) => last.created_at < cutoff,
+                _ => false,
+            };
+            all.extend(
+                issues
+                    .nodes
+                    .into_iter()
+                    .map(|node| node.into_issue(repo, &self.web_base_url)),
+            );
+            if done || !issues.page_info.has_next_page {
+                break;
+            }
+            cursor = issues.page_info.end_cursor;
+        }
+        Ok(all)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn issue_nodes() {
+        let res = r#"{"data": {"repository": {"issues": {
+            "pageInfo": {"hasNextPage": false, "endCursor": null},
+            "nodes": [
+                {"number": 1, "title": "t1", "createdAt": "2020-05-01T00:00:00Z",
+                 "author": {"login": "a"}, "authorAssociation": "NONE",
+                 "labels": {"nodes": [{"name": "bug", "description": null}]},
+                 "assignees": {"nodes": []},
+                 "comments": {"totalCount": 2, "nodes": [
+                     {"authorAssociation": "NONE"}, {"authorAssociation": "MEMBER"}]}},
+                {"number": 2, "title": "t2", "createdAt": "2020-05-01T00:00:00Z",
+                 "author": null, "authorAssociation": "NONE",
+                 "labels": {"nodes": []},
+                 "assignees": {"nodes": [{"databaseId": 7, "login": "b"}]},
+                 "comments": {"totalCount": 101, "nodes": [{"authorAssociation": "NONE"}]}}
+            ]}}}}"#;
+        let res: Response<IssuesData> = serde_json::from_str(res).unwrap();
+        let repo = Repo::try_from("o/r").unwrap();
+        let issues: Vec<Issue> = res
+            .data
+            .unwrap()
+            .repository
+            .unwrap()
+            .issues
+            .nodes
+            .into_iter()
+            .map(|node| node.into_issue(&repo, "https://github.com"))
+            .collect();
+        assert_eq!(issues[0].to_string(), "https://github.com/o/r/issues/1");
+        assert_eq!(issues[0].labels[0].name, "bug");
+        assert!(issues[0].assignee.is_none());
+        assert_eq!(issues[0].member_comments, Some(1));
+        assert_eq!(issues[1].user.login, "ghost");
+        assert_eq!(issues[1].assignee.as_ref().unwrap().login, "b");
+        // more comments than fetched, none from members
+        assert_eq!(issues[1].member_comments, None);
+    }
+
+    #[test]
+    fn graphql_urls() {
+        assert_eq!(
+            graphql_url("https://api.github.com"),
+            "https://api.github.com/graphql"
+        );
+        assert_eq!(
+            graphql_url("https://github.example.com/api/v3"),
+            "https://github.example.com/api/graphql"
+        );
+    }
+}
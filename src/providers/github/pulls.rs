@@ -0,0 +1,275 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use serde::Deserialize;
+
+use super::{fetch_by_repo, if_member, GitHub, Header, Label, Repo, User, PER_PAGE};
+use crate::error::Result;
+use crate::providers::{Failure, Fetched, Item, Source, SourceKind, Tracked};
+
+/// Draft pull requests were a preview when this was written.
+const ACCEPT: &str = "application/vnd.github.shadow-cat-preview+json";
+
+/// Checks the pull requests of a GitHub instance instead of its issues.
+pub struct PullRequests {
+    github: GitHub,
+}
+
+#[derive(Deserialize)]
+pub struct PullRequest {
+    pub(super) number: i32,
+    pub(super) title: String,
+    pub(super) html_url: String,
+    pub(super) user: User,
+    pub(super) author_association: String,
+    #[serde(default)]
+    pub(super) draft: bool,
+    pub(super) created_at: DateTime<Utc>,
+    pub(super) labels: Vec<Label>,
+    /// reviewers who have not reviewed yet
+    #[serde(default)]
+    pub(super) requested_reviewers: Vec<User>,
+    #[serde(default)]
+    pub(super) requested_teams: Vec<Team>,
+    #[serde(skip_deserializing)]
+    pub(super) owner: String,
+    #[serde(skip_deserializing)]
+    pub(super) repo: String,
+}
+
+#[derive(Deserialize)]
+pub struct Team {
+    pub(super) slug: String,
+}
+
+#[derive(Deserialize)]
+pub struct Review {
+    #[serde(default)]
+    pub(super) author_association: String,
+    /// none if the account was deleted
+    pub(super) user: Option<User>,
+    /// none if the review is still pending
+    pub(super) submitted_at: Option<DateTime<Utc>>,
+}
+
+impl From<PullRequest> for Item {
+    fn from(pull: PullRequest) -> Self {
+        let mut reviewers: Vec<String> = pull
+            .requested_reviewers
+            .into_iter()
+            .map(|user| user.login)
+            .collect();
+        let owner = &pull.owner;
+        reviewers.extend(
+            pull.requested_teams
+                .iter()
+                .map(|team| format!("{}/{}", owner, team.slug)),
+        );
+        Item {
+            url: pull.html_url,
+            title: pull.title,
+            author: pull.user.login,
+            created_at: pull.created_at,
+            kind: SourceKind::GitHub,
+            group: format!("{}/{}", pull.owner, pull.repo),
+            tags: pull.labels.into_iter().map(|label| label.name).collect(),
+            channel: None,
+            owners: vec![],
+            reviewers,
+        }
+    }
+}
+
+impl PullRequests {
+    pub fn new(github: GitHub) -> Self {
+        PullRequests { github }
+    }
+}
+
+impl GitHub {
+    /// Returns the pull requests of non-members which are waiting for a
+    /// first review, the urls of the drafts and the ones filtered by label,
+    /// and the repos and pull requests which could not be checked.
+    async fn get_unreviewed_pulls(
+        &self,
+        repos: Vec<Repo>,
+        tracked: &Tracked,
+    ) -> (Vec<PullRequest>, Vec<String>, Vec<Failure>) {
+        let now = Utc::now();
+        let (opened_all, mut failures) = fetch_by_repo(&repos, "pull requests", |repo| {
+            self.list_opened_pulls(repo, self.cutoff_of_repo(repo, tracked, now))
+        })
+        .await;
+
+        let mut skipped = vec![];
+        let opened_pulls: Vec<PullRequest> = opened_all
+            .into_iter()
+            .filter(|pull| {
+                let (owner, repo) = (&pull.owner[..], &pull.repo[..]);
+                if if_member(&pull.author_association)
+                    || !tracked.admits(
+                        self.window_of_repo(owner, repo),
+                        &pull.html_url,
+                        pull.created_at,
+                        now,
+                    )
+                {
+                    return false;
+                }
+                // left out while still waiting, so not taken as resolved
+                if pull.draft
+                    || self.if_filter_by_labels(owner, repo, &pull.labels)
+                    || !self.if_include_by_labels(owner, repo, &pull.labels)
+                {
+                    skipped.push(pull.html_url.to_owned());
+                    return false;
+                }
+                true
+            })
+            .collect();
+
+        let mut unreviewed = vec![];
+        let replies = join_all(
+            opened_pulls
+                .iter()
+                .map(|pull| self.get_member_replies(pull)),
+        )
+        .await;
+        for (pull, replies) in opened_pulls.into_iter().zip(replies) {
+            match replies {
+                Ok(0) => unreviewed.push(pull),
+                Ok(_) => {}
+                Err(e) => {
+                    println!("Failed to check reviews of {}: {}", pull.html_url, e);
+                    failures.push(Failure {
+                        target: pull.html_url.to_owned(),
+                        reason: e.to_string(),
+                    });
+                }
+            }
+        }
+        (unreviewed, skipped, failures)
+    }
+
+    /// Lists the open pull requests of a repo, only the ones created after
+    /// `cutoff` if set.
+    pub(super) async fn list_opened_pulls(
+        &self,
+        repo: &Repo,
+        cutoff: Option<DateTime<Utc>>,
+    ) -> Result<Vec<PullRequest>> {
+        // newest first, so paging can stop at the first one out of the window
+        let url = format!(
+            "{}/repos/{}/{}/pulls?state=open&sort=created&direction=desc&per_page={}",
+            self.api_base_url, repo.owner, repo.repo, PER_PAGE
+        );
+        let headers = vec![
+            self.authorization(&repo.owner).await?,
+            Header {
+                key: "Accept".to_owned(),
+                value: ACCEPT.to_owned(),
+            },
+        ];
+        let all: Vec<PullRequest> = match cutoff {
+            Some(cutoff) => {
+                self.request_until(url, headers, |pull: &PullRequest| pull.created_at < cutoff)
+                    .await?
+            }
+            None => self.request_all(url, headers).await?,
+        };
+        Ok(all
+            .into_iter()
+            .map(|mut pull| {
+                pull.owner = repo.owner.to_owned();
+                pull.repo = repo.repo.to_owned();
+                pull
+            })
+            .collect())
+    }
+
+    /// Lists the reviews of a pull request.
+    pub(super) async fn get_reviews(&self, pull: &PullRequest) -> Result<Vec<Review>> {
+        let url = format!(
+            "{}/repos/{}/{}/pulls/{}/reviews?per_page={}",
+            self.api_base_url, pull.owner, pull.repo, pull.number, PER_PAGE
+        );
+        let headers = vec![self.authorization(&pull.owner).await?];
+        self.request_all(url, headers).await
+    }
+
+    /// Counts the reviews and comments of members on a pull request.
+    async fn get_member_replies(&self, pull: &PullRequest) -> Result<usize> {
+        let reviews = self.get_reviews(pull).await?;
+        let member_reviews = reviews
+            .iter()
+            .filter(|review| if_member(&review.author_association))
+            .count();
+        if member_reviews > 0 {
+            return Ok(member_reviews);
+        }
+        self.get_member_comments(&pull.owner, &pull.repo, pull.number)
+            .await
+    }
+}
+
+#[async_trait]
+impl Source for PullRequests {
+    fn kind(&self) -> SourceKind {
+        SourceKind::GitHub
+    }
+
+    fn name(&self) -> String {
+        format!("{}-pulls", self.github.name)
+    }
+
+    fn describe(&self) -> String {
+        self.github.describe_as("unreviewed pull requests")
+    }
+
+    async fn fetch(&self, tracked: &Tracked) -> Result<Fetched> {
+        let github = &self.github;
+        github.start_fetch().await?;
+        let (repos, mut failures) = github.expand_repos().await;
+        let (pulls, skipped, pull_failures) = github.get_unreviewed_pulls(repos, tracked).await;
+        failures.extend(pull_failures);
+        github.print_quota();
+        github.save_cache();
+        let items = pulls
+            .into_iter()
+            .map(|pull| {
+                let (owner, repo) = (pull.owner.to_owned(), pull.repo.to_owned());
+                github.with_rule(&owner, &repo, pull.into())
+            })
+            .collect();
+        Ok(Fetched {
+            items,
+            failures,
+            skipped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pull_items() {
+        let pulls = r#"[{"number": 1, "title": "t1", "html_url": "https://github.com/o/r/pull/1",
+            "user": {"login": "a"}, "author_association": "NONE", "draft": false,
+            "created_at": "2020-05-01T00:00:00Z",
+            "labels": [{"id": 1, "name": "bug", "description": null}],
+            "requested_reviewers": [{"login": "b"}],
+            "requested_teams": [{"slug": "reviewers"}]}]"#;
+        let mut pulls: Vec<PullRequest> = serde_json::from_str(pulls).unwrap();
+        let mut pull = pulls.remove(0);
+        pull.owner = "o".to_owned();
+        pull.repo = "r".to_owned();
+        assert!(!pull.draft);
+        let item: Item = pull.into();
+        assert_eq!(item.url, "https://github.com/o/r/pull/1");
+        assert_eq!(item.group, "o/r");
+        assert_eq!(item.tags, vec!["bug"]);
+        assert_eq!(item.reviewers, vec!["b", "o/reviewers"]);
+    }
+}
@@ -0,0 +1,229 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::Deserialize;
+
+/// Retries of a request which keeps being rate limited.
+pub const MAX_RETRIES: u32 = 3;
+
+/// The quota of a rate limit resource, like "core" for REST or "graphql".
+#[derive(Default)]
+struct Quota {
+    limit: Option<u64>,
+    remaining: Option<u64>,
+    reset: Option<DateTime<Utc>>,
+    /// requests since the start of the run
+    requests: u32,
+}
+
+/// Tracks the quota left from the `X-RateLimit-*` headers of responses.
+#[derive(Default)]
+pub struct RateLimits {
+    quotas: HashMap<String, Quota>,
+}
+
+impl RateLimits {
+    /// Records the quota from a response to a request of `resource`.
+    pub fn update(&mut self, resource: &str, headers: &HeaderMap) {
+        let quota = self.quotas.entry(resource.to_owned()).or_default();
+        quota.requests += 1;
+        if let Some(limit) = header_u64(headers, "x-ratelimit-limit") {
+            quota.limit = Some(limit);
+        }
+        if let Some(remaining) = header_u64(headers, "x-ratelimit-remaining") {
+            quota.remaining = Some(remaining);
+        }
+        if let Some(reset) = header_u64(headers, "x-ratelimit-reset") {
+            quota.reset = Utc.timestamp_opt(reset as i64, 0).single();
+        }
+    }
+
+    /// Returns how long to wait for the quota of `resource` to reset, if it
+    /// is exhausted.
+    pub fn wait(&self, resource: &str, now: DateTime<Utc>) -> Option<Duration> {
+        let quota = self.quotas.get(resource)?;
+        match (quota.remaining, quota.reset) {
+            (Some(0), Some(reset)) if reset > now => Some(until(reset, now)),
+            _ => None,
+        }
+    }
+
+    /// Starts counting the requests of a new run.
+    pub fn start_run(&mut self) {
+        for quota in self.quotas.values_mut() {
+            quota.requests = 0;
+        }
+    }
+
+    /// Describes the usage of each resource, e.g.
+    /// "core: 57 requests, 4943/5000 left until 12:00:00 UTC".
+    pub fn summary(&self) -> Vec<String> {
+        let mut resources: Vec<&String> = self.quotas.keys().collect();
+        resources.sort();
+        resources
+            .into_iter()
+            .map(|resource| {
+                let quota = &self.quotas[resource];
+                let mut line = format!("{}: {} requests", resource, quota.requests);
+                if let (Some(remaining), Some(limit)) = (quota.remaining, quota.limit) {
+                    line.push_str(&format!(", {}/{} left", remaining, limit));
+                }
+                if let Some(reset) = quota.reset {
+                    line.push_str(&format!(" until {}", reset.format("%H:%M:%S UTC")));
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+/// Returns how long to back off before retrying a response, or `None` if it
+/// was not rate limited. Secondary rate limits are 403s, which may come
+/// without `Retry-After`, then the wait doubles from a minute on each retry.
+/// Rate limited GraphQL queries are 200s with an error of type
+/// `RATE_LIMITED`.
+pub fn backoff(
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &str,
+    retries: u32,
+    now: DateTime<Utc>,
+) -> Option<Duration> {
+    match status {
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => {}
+        StatusCode::OK if graphql_rate_limited(body) => {}
+        _ => return None,
+    }
+    if let Some(secs) = header_u64(headers, "retry-after") {
+        return Some(Duration::from_secs(secs));
+    }
+    if header_u64(headers, "x-ratelimit-remaining") == Some(0) {
+        let reset = header_u64(headers, "x-ratelimit-reset")
+            .and_then(|reset| Utc.timestamp_opt(reset as i64, 0).single());
+        if let Some(reset) = reset {
+            return Some(until(reset, now));
+        }
+    }
+    if status != StatusCode::FORBIDDEN || body.to_lowercase().contains("rate limit") {
+        return Some(Duration::from_secs(60 << retries));
+    }
+    None
+}
+
+/// Whether `body` is a GraphQL response with a `RATE_LIMITED` error.
+fn graphql_rate_limited(body: &str) -> bool {
+    #[derive(Deserialize)]
+    struct Response {
+        #[serde(default)]
+        errors: Vec<QueryError>,
+    }
+    #[derive(Deserialize)]
+    struct QueryError {
+        #[serde(rename = "type")]
+        kind: Option<String>,
+    }
+    // most bodies are not, so they are not parsed
+    body.contains("RATE_LIMITED")
+        && serde_json::from_str::<Response>(body).is_ok_and(|res| {
+            res.errors
+                .iter()
+                .any(|e| e.kind.as_deref() == Some("RATE_LIMITED"))
+        })
+}
+
+/// The wait until `reset`, with a second to spare for clock skew.
+fn until(reset: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+    reset
+        .signed_duration_since(now)
+        .to_std()
+        .unwrap_or_default()
+        + Duration::from_secs(1)
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn new_headers(pairs: Vec<(&'static str, &str)>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn rate_limits() {
+        let now = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        let mut limits = RateLimits::default();
+        limits.update(
+            "core",
+            &new_headers(vec![
+                ("x-ratelimit-limit", "5000"),
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-reset", "1600000060"),
+            ]),
+        );
+        assert_eq!(limits.wait("core", now), Some(Duration::from_secs(61)));
+        assert_eq!(limits.wait("graphql", now), None);
+        assert_eq!(
+            limits.summary(),
+            vec!["core: 1 requests, 0/5000 left until 12:27:40 UTC"]
+        );
+        limits.start_run();
+        limits.update(
+            "core",
+            &new_headers(vec![("x-ratelimit-remaining", "4999")]),
+        );
+        assert_eq!(limits.wait("core", now), None);
+        assert_eq!(
+            limits.summary(),
+            vec!["core: 1 requests, 4999/5000 left until 12:27:40 UTC"]
+        );
+    }
+
+    #[test]
+    fn backoffs() {
+        let now = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        let ok = new_headers(vec![]);
+        assert_eq!(backoff(StatusCode::OK, &ok, "", 0, now), None);
+        // permission denied is not retried
+        assert_eq!(backoff(StatusCode::FORBIDDEN, &ok, "denied", 0, now), None);
+        let retry = new_headers(vec![("retry-after", "30")]);
+        assert_eq!(
+            backoff(StatusCode::FORBIDDEN, &retry, "", 0, now),
+            Some(Duration::from_secs(30))
+        );
+        let exhausted = new_headers(vec![
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-reset", "1600000010"),
+        ]);
+        assert_eq!(
+            backoff(StatusCode::FORBIDDEN, &exhausted, "", 0, now),
+            Some(Duration::from_secs(11))
+        );
+        let body = "You have exceeded a secondary rate limit.";
+        assert_eq!(
+            backoff(StatusCode::FORBIDDEN, &ok, body, 2, now),
+            Some(Duration::from_secs(240))
+        );
+        let body =
+            r#"{"errors": [{"type": "RATE_LIMITED", "message": "API rate limit exceeded"}]}"#;
+        assert_eq!(
+            backoff(StatusCode::OK, &exhausted, body, 0, now),
+            Some(Duration::from_secs(11))
+        );
+        assert_eq!(
+            backoff(StatusCode::OK, &ok, body, 1, now),
+            Some(Duration::from_secs(120))
+        );
+        let body = r#"{"errors": [{"type": "NOT_FOUND", "message": "RATE_LIMITED"}]}"#;
+        assert_eq!(backoff(StatusCode::OK, &ok, body, 0, now), None);
+    }
+}
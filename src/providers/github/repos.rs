@@ -0,0 +1,225 @@
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    fmt,
+};
+
+use serde::Deserialize;
+
+use super::{api::ApiError, Failure, GitHub, Repo, PER_PAGE};
+use crate::error::{Error, Result};
+
+/// An entry of `repos` or `exclude-repos`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RepoPattern {
+    /// "owner/repo"
+    Repo(Repo),
+    /// "owner/prefix-*", or "org:owner" for all the repos of an owner
+    Glob { owner: String, name: String },
+}
+
+impl TryFrom<&str> for RepoPattern {
+    type Error = String;
+
+    fn try_from(s: &str) -> std::result::Result<Self, String> {
+        if let Some(owner) = s.trim().strip_prefix("org:") {
+            if owner.is_empty() || owner.contains('/') || owner.contains('*') {
+                return Err(format!("invalid repo {:?}, expected \"org:owner\"", s));
+            }
+            return Ok(RepoPattern::Glob {
+                owner: owner.to_owned(),
+                name: "*".to_owned(),
+            });
+        }
+        let repo = Repo::try_from(s)?;
+        if repo.owner.contains('*') {
+            return Err(format!(
+                "invalid repo {:?}, wildcards are only allowed in repo names",
+                s
+            ));
+        }
+        if repo.repo.contains('*') {
+            return Ok(RepoPattern::Glob {
+                owner: repo.owner,
+                name: repo.repo,
+            });
+        }
+        Ok(RepoPattern::Repo(repo))
+    }
+}
+
+impl fmt::Display for RepoPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepoPattern::Repo(repo) => write!(f, "{}/{}", repo.owner, repo.repo),
+            RepoPattern::Glob { owner, name } => write!(f, "{}/{}", owner, name),
+        }
+    }
+}
+
+impl RepoPattern {
+    /// Matches case-insensitively, like GitHub does.
+    pub fn matches(&self, owner: &str, repo: &str) -> bool {
+        match self {
+            RepoPattern::Repo(r) => {
+                r.owner.eq_ignore_ascii_case(owner) && r.repo.eq_ignore_ascii_case(repo)
+            }
+            RepoPattern::Glob { owner: o, name } => {
+                o.eq_ignore_ascii_case(owner) && glob_match(name, repo)
+            }
+        }
+    }
+}
+
+/// Matches `text` against a pattern where `*` stands for any characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let text = text.to_lowercase();
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if parts.len() == 1 {
+        return text == pattern;
+    }
+    if text.len() < first.len() + last.len() || !text.starts_with(first) || !text.ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// A repo listed by the repos API.
+#[derive(Deserialize)]
+struct RepoInfo {
+    name: String,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    fork: bool,
+}
+
+impl GitHub {
+    /// Returns the repos to check, in the order of `repos` with the globs
+    /// expanded, and the globs whose owners could not be listed. Archived
+    /// repos and forks are only checked if listed by name, or if enabled.
+    pub(super) async fn expand_repos(&self) -> (Vec<Repo>, Vec<Failure>) {
+        let mut repos = vec![];
+        let mut failures = vec![];
+        let mut seen = HashSet::new();
+        // owners are listed once, even if several globs match their repos
+        let mut listed: HashMap<String, Vec<RepoInfo>> = HashMap::new();
+        for pattern in &self.patterns {
+            let matched = match pattern {
+                RepoPattern::Repo(repo) => vec![repo.clone()],
+                RepoPattern::Glob { owner, .. } => {
+                    let key = owner.to_lowercase();
+                    if !listed.contains_key(&key) {
+                        match self.list_repos_of(owner).await {
+                            Ok(infos) => {
+                                listed.insert(key.to_owned(), infos);
+                            }
+                            Err(e) => {
+                                println!("Failed to list the repos of {}: {}", owner, e);
+                                failures.push(Failure {
+                                    target: pattern.to_string(),
+                                    reason: e.to_string(),
+                                });
+                                continue;
+                            }
+                        }
+                    }
+                    listed[&key]
+                        .iter()
+                        .filter(|info| pattern.matches(owner, &info.name))
+                        .filter(|info| !info.archived || self.repo_filter.include_archived)
+                        .filter(|info| !info.fork || self.repo_filter.include_forks)
+                        .map(|info| Repo {
+                            owner: owner.to_owned(),
+                            repo: info.name.to_owned(),
+                        })
+                        .collect()
+                }
+            };
+            for repo in matched {
+                let excluded = self
+                    .exclusions
+                    .iter()
+                    .any(|exclusion| exclusion.matches(&repo.owner, &repo.repo));
+                let name = format!("{}/{}", repo.owner, repo.repo).to_lowercase();
+                if !excluded && seen.insert(name) {
+                    repos.push(repo);
+                }
+            }
+        }
+        (repos, failures)
+    }
+
+    /// Lists the repos of an organization, or of a user if it is not one.
+    async fn list_repos_of(&self, owner: &str) -> Result<Vec<RepoInfo>> {
+        let headers = vec![self.authorization(owner).await?];
+        let url = format!(
+            "{}/orgs/{}/repos?type=all&per_page={}",
+            self.api_base_url, owner, PER_PAGE
+        );
+        match self.request_all(url, headers.clone()).await {
+            Err(Error::Api(ApiError::NotFound { .. })) => {
+                let url = format!(
+                    "{}/users/{}/repos?type=owner&per_page={}",
+                    self.api_base_url, owner, PER_PAGE
+                );
+                self.request_all(url, headers).await
+            }
+            res => res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        let parse = |s| RepoPattern::try_from(s);
+        assert_eq!(
+            parse("o/r"),
+            Ok(RepoPattern::Repo(Repo {
+                owner: "o".to_owned(),
+                repo: "r".to_owned(),
+            }))
+        );
+        assert_eq!(
+            parse("org:tikv"),
+            Ok(RepoPattern::Glob {
+                owner: "tikv".to_owned(),
+                name: "*".to_owned(),
+            })
+        );
+        for invalid in &["o", "o/", "/r", "o/r/x", "org:", "org:o/r", "*/r", ""] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+
+        let glob = parse("pingcap/tidb-*").unwrap();
+        assert_eq!(parse("org:tikv").unwrap().to_string(), "tikv/*");
+        assert!(glob.matches("PingCAP", "tidb-operator"));
+        assert!(glob.matches("pingcap", "tidb-"));
+        assert!(!glob.matches("pingcap", "tidb"));
+        assert!(!glob.matches("tikv", "tidb-operator"));
+        assert!(parse("o/R").unwrap().matches("O", "r"));
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("*-docs", "tidb-docs"));
+        assert!(glob_match("t*-*s", "tidb-docs"));
+        assert!(!glob_match("t*-*s", "tidb-doc"));
+        assert!(!glob_match("a*a", "a"));
+        assert!(glob_match("tidb", "TiDB"));
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use futures::future::join_all;
+use serde::Deserialize;
+
+use super::{
+    fetch_by_repo,
+    pulls::{PullRequest, Review, Team},
+    GitHub, Repo, User, PER_PAGE,
+};
+use crate::config::format_duration;
+use crate::error::{Error, Result};
+use crate::providers::{Failure, Fetched, Item, Source, SourceKind, Tracked};
+
+/// Checks the review requests of a GitHub instance which have been pending
+/// for too long, so the requested reviewers can be reminded.
+pub struct ReviewRequests {
+    github: GitHub,
+    /// requests pending for longer than this are reported
+    after: Duration,
+}
+
+/// An event of the issue of a pull request.
+#[derive(Deserialize)]
+struct Event {
+    event: String,
+    created_at: DateTime<Utc>,
+    requested_reviewer: Option<User>,
+    requested_team: Option<Team>,
+}
+
+/// A pull request with the reviewers whose requests are stale, and when
+/// each was requested.
+struct StaleRequests {
+    pull: PullRequest,
+    reviewers: Vec<(String, DateTime<Utc>)>,
+}
+
+impl From<StaleRequests> for Item {
+    fn from(stale: StaleRequests) -> Self {
+        let pull = stale.pull;
+        // the age of the item is that of its oldest stale request
+        let requested_at = stale
+            .reviewers
+            .iter()
+            .map(|(_, requested_at)| *requested_at)
+            .min()
+            .unwrap_or(pull.created_at);
+        Item {
+            url: pull.html_url,
+            title: pull.title,
+            author: pull.user.login,
+            created_at: requested_at,
+            kind: SourceKind::GitHub,
+            group: format!("{}/{}", pull.owner, pull.repo),
+            tags: pull.labels.into_iter().map(|label| label.name).collect(),
+            channel: None,
+            owners: vec![],
+            reviewers: stale
+                .reviewers
+                .into_iter()
+                .map(|(reviewer, _)| reviewer)
+                .collect(),
+        }
+    }
+}
+
+impl ReviewRequests {
+    pub fn new(github: GitHub, after: Duration) -> Self {
+        ReviewRequests { github, after }
+    }
+
+    /// Returns the pull requests with stale review requests, the urls of the
+    /// drafts and the ones filtered by label, and the repos and pull requests
+    /// which could not be checked.
+    async fn get_stale_requests(
+        &self,
+        repos: Vec<Repo>,
+    ) -> (Vec<StaleRequests>, Vec<String>, Vec<Failure>) {
+        let github = &self.github;
+        let now = Utc::now();
+        // a request may be pending on a pull request of any age
+        let (requested_all, mut failures) = fetch_by_repo(&repos, "review requests", |repo| {
+            github.list_opened_pulls(repo, None)
+        })
+        .await;
+
+        let mut skipped = vec![];
+        let requested: Vec<PullRequest> = requested_all
+            .into_iter()
+            .filter(|pull| {
+                let (owner, repo) = (&pull.owner[..], &pull.repo[..]);
+                if (pull.requested_reviewers.is_empty() && pull.requested_teams.is_empty())
+                    // no request can be stale yet
+                    || now - pull.created_at <= self.after
+                {
+                    return false;
+                }
+                // left out while still waiting, so not taken as resolved
+                if pull.draft
+                    || github.if_filter_by_labels(owner, repo, &pull.labels)
+                    || !github.if_include_by_labels(owner, repo, &pull.labels)
+                {
+                    skipped.push(pull.html_url.to_owned());
+                    return false;
+                }
+                true
+            })
+            .collect();
+
+        let mut stale = vec![];
+        let results = join_all(requested.iter().map(|pull| async move {
+            let events = github.get_events(pull).await?;
+            let reviews = github.get_reviews(pull).await?;
+            Ok::<_, Error>(stale_reviewers(pull, &events, &reviews, self.after, now))
+        }))
+        .await;
+        for (pull, reviewers) in requested.into_iter().zip(results) {
+            match reviewers {
+                Ok(reviewers) if reviewers.is_empty() => {}
+                Ok(reviewers) => stale.push(StaleRequests { pull, reviewers }),
+                Err(e) => {
+                    println!(
+                        "Failed to check review requests of {}: {}",
+                        pull.html_url, e
+                    );
+                    failures.push(Failure {
+                        target: pull.html_url.to_owned(),
+                        reason: e.to_string(),
+                    });
+                }
+            }
+        }
+        (stale, skipped, failures)
+    }
+}
+
+impl GitHub {
+    /// Lists the events of a pull request, which tell when reviews were
+    /// requested.
+    async fn get_events(&self, pull: &PullRequest) -> Result<Vec<Event>> {
+        let url = format!(
+            "{}/repos/{}/{}/issues/{}/events?per_page={}",
+            self.api_base_url, pull.owner, pull.repo, pull.number, PER_PAGE
+        );
+        let headers = vec![self.authorization(&pull.owner).await?];
+        self.request_all(url, headers).await
+    }
+}
+
+/// Returns the reviewers of `pull` whose requests are older than
+/// `after`, as logins or "owner/team". A request is pending while the
+/// reviewer is still requested and has not reviewed since it was made.
+fn stale_reviewers(
+    pull: &PullRequest,
+    events: &[Event],
+    reviews: &[Review],
+    after: Duration,
+    now: DateTime<Utc>,
+) -> Vec<(String, DateTime<Utc>)> {
+    // the latest request of each reviewer, a request may be made again
+    let mut requested: HashMap<String, DateTime<Utc>> = HashMap::new();
+    for event in events
+        .iter()
+        .filter(|event| event.event == "review_requested")
+    {
+        let reviewer = match (&event.requested_reviewer, &event.requested_team) {
+            (Some(user), _) => user.login.to_lowercase(),
+            (None, Some(team)) => format!("{}/{}", pull.owner, team.slug).to_lowercase(),
+            (None, None) => continue,
+        };
+        let at = requested.entry(reviewer).or_insert(event.created_at);
+        if event.created_at > *at {
+            *at = event.created_at;
+        }
+    }
+
+    let users = pull.requested_reviewers.iter().map(|user| {
+        let login = user.login.to_owned();
+        let reviewed_at = reviews
+            .iter()
+            .filter(|review| match &review.user {
+                Some(user) => user.login.eq_ignore_ascii_case(&login),
+                None => false,
+            })
+            .filter_map(|review| review.submitted_at)
+            .max();
+        (login, reviewed_at)
+    });
+    let teams = pull
+        .requested_teams
+        .iter()
+        .map(|team| (format!("{}/{}", pull.owner, team.slug), None));
+    users
+        .chain(teams)
+        .filter_map(|(reviewer, reviewed_at)| {
+            // requests whose events are missing count from the creation of
+            // the pull request
+            let requested_at = requested
+                .get(&reviewer.to_lowercase())
+                .copied()
+                .unwrap_or(pull.created_at);
+            let reviewed = matches!(reviewed_at, Some(at) if at > requested_at);
+            if !reviewed && now - requested_at > after {
+                Some((reviewer, requested_at))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Source for ReviewRequests {
+    fn kind(&self) -> SourceKind {
+        SourceKind::GitHub
+    }
+
+    fn name(&self) -> String {
+        format!("{}-reviews", self.github.name)
+    }
+
+    fn describe(&self) -> String {
+        self.github.describe_as(&format!(
+            "review requests pending over {}",
+            format_duration(self.after)
+        ))
+    }
+
+    /// Review requests have no window, so every pending one is checked.
+    async fn fetch(&self, _tracked: &Tracked) -> Result<Fetched> {
+        let github = &self.github;
+        github.start_fetch().await?;
+        let (repos, mut failures) = github.expand_repos().await;
+        let (stale, skipped, stale_failures) = self.get_stale_requests(repos).await;
+        failures.extend(stale_failures);
+        github.print_quota();
+        github.save_cache();
+        let items = stale
+            .into_iter()
+            .map(|stale| {
+                let pull = &stale.pull;
+                let (owner, repo) = (pull.owner.to_owned(), pull.repo.to_owned());
+                github.with_rule(&owner, &repo, stale.into())
+            })
+            .collect();
+        Ok(Fetched {
+            items,
+            failures,
+            skipped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale() {
+        let pull = r#"{"number": 1, "title": "t1", "html_url": "https://github.com/o/r/pull/1",
+            "user": {"login": "a"}, "author_association": "MEMBER",
+            "created_at": "2020-05-01T00:00:00Z", "labels": [],
+            "requested_reviewers": [{"login": "b"}, {"login": "c"}, {"login": "d"}],
+            "requested_teams": [{"slug": "reviewers"}]}"#;
+        let mut pull: PullRequest = serde_json::from_str(pull).unwrap();
+        pull.owner = "o".to_owned();
+        pull.repo = "r".to_owned();
+        let events = r#"[
+            {"event": "review_requested", "created_at": "2020-05-01T00:00:00Z",
+             "requested_reviewer": {"login": "b"}},
+            {"event": "review_requested", "created_at": "2020-05-01T00:00:00Z",
+             "requested_reviewer": {"login": "c"}},
+            {"event": "labeled", "created_at": "2020-05-02T00:00:00Z"},
+            {"event": "review_requested", "created_at": "2020-05-04T00:00:00Z",
+             "requested_reviewer": {"login": "C"}},
+            {"event": "review_requested", "created_at": "2020-05-02T00:00:00Z",
+             "requested_team": {"slug": "reviewers"}},
+            {"event": "review_requested", "created_at": "2020-05-01T00:00:00Z",
+             "requested_reviewer": {"login": "d"}}]"#;
+        let events: Vec<Event> = serde_json::from_str(events).unwrap();
+        let reviews = r#"[{"user": {"login": "d"}, "submitted_at": "2020-05-02T00:00:00Z"},
+            {"user": {"login": "b"}, "submitted_at": null}, {"user": null}]"#;
+        let reviews: Vec<Review> = serde_json::from_str(reviews).unwrap();
+        let now = "2020-05-05T00:00:00Z".parse().unwrap();
+        let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+
+        // c was requested again a day ago, d has reviewed since
+        let stale = stale_reviewers(&pull, &events, &reviews, Duration::days(2), now);
+        assert_eq!(
+            stale,
+            vec![
+                ("b".to_owned(), at("2020-05-01T00:00:00Z")),
+                ("o/reviewers".to_owned(), at("2020-05-02T00:00:00Z")),
+            ]
+        );
+
+        let item: Item = StaleRequests {
+            pull,
+            reviewers: stale,
+        }
+        .into();
+        assert_eq!(item.url, "https://github.com/o/r/pull/1");
+        assert_eq!(item.reviewers, vec!["b", "o/reviewers"]);
+        assert_eq!(item.created_at, at("2020-05-01T00:00:00Z"));
+    }
+}
@@ -1,3 +1,408 @@
+pub mod api;
 pub mod discourse;
 pub mod github;
 pub mod slack;
+pub mod stdout;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::Semaphore;
+
+use crate::config::{Config, NotifierConfig, Window};
+use crate::error::{Context, Result};
+use crate::report::Report;
+use crate::template::Templates;
+use discourse::Discourse;
+use github::{App, Auth, Cache, GitHub, PullRequests, ReviewRequests};
+use slack::{Slack, SlackReviewers};
+use stdout::StdoutNotifier;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SourceKind {
+    GitHub,
+    Discourse,
+}
+
+impl fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceKind::GitHub => write!(f, "github"),
+            SourceKind::Discourse => write!(f, "discourse"),
+        }
+    }
+}
+
+/// An item which is still waiting for a reply, normalized across sources.
+#[derive(Clone)]
+pub struct Item {
+    pub title: String,
+    pub url: String,
+    pub author: String,
+    pub created_at: DateTime<Utc>,
+    pub kind: SourceKind,
+    /// the repo of an issue or the category of a topic
+    pub group: String,
+    pub tags: Vec<String>,
+    /// overrides the channel of notifiers
+    pub channel: Option<String>,
+    pub owners: Vec<String>,
+    /// the users and teams a review of a pull request is requested from
+    pub reviewers: Vec<String>,
+}
+
+/// An issue of "o/r" by you06 titled by its url, for tests to fill in the
+/// rest with struct update syntax.
+#[cfg(test)]
+pub fn new_test_item(url: &str, created_at: DateTime<Utc>) -> Item {
+    Item {
+        title: url.to_owned(),
+        url: url.to_owned(),
+        author: "you06".to_owned(),
+        created_at,
+        kind: SourceKind::GitHub,
+        group: "o/r".to_owned(),
+        tags: vec![],
+        channel: None,
+        owners: vec![],
+        reviewers: vec![],
+    }
+}
+
+/// Something a source could not check, e.g. a repo whose issues failed to load.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failure {
+    /// e.g. "you06/pingbot", or the source if it failed as a whole
+    pub target: String,
+    pub reason: String,
+}
+
+/// The items a source found, and what it could not check.
+#[derive(Default)]
+pub struct Fetched {
+    pub items: Vec<Item>,
+    pub failures: Vec<Failure>,
+    /// urls of items which were seen unanswered but left out, e.g. filtered
+    /// by label, so they are not taken as resolved
+    pub skipped: Vec<String>,
+}
+
+/// The items reported before which are still waiting for a reply. Sources
+/// keep checking them past `max-age`, which only bounds the items seen for
+/// the first time, so they escalate instead of dropping out of reports.
+#[derive(Default)]
+pub struct Tracked {
+    urls: HashSet<String>,
+    /// the creation time of the oldest item of each group
+    oldest: HashMap<String, DateTime<Utc>>,
+}
+
+impl Tracked {
+    /// Tracks the items given by their urls, groups and creation times.
+    pub fn new<'a>(items: impl IntoIterator<Item = (&'a str, &'a str, DateTime<Utc>)>) -> Self {
+        let mut tracked = Tracked::default();
+        for (url, group, created_at) in items {
+            tracked.urls.insert(url.to_owned());
+            let oldest = tracked.oldest.entry(group.to_owned()).or_insert(created_at);
+            *oldest = (*oldest).min(created_at);
+        }
+        tracked
+    }
+
+    /// Returns whether an item is checked, tracked ones even out of the window.
+    pub fn admits(
+        &self,
+        window: Window,
+        url: &str,
+        created_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        self.urls.contains(url) || window.contains(created_at, now)
+    }
+
+    /// Returns when listing the items of a group newest first can stop, at
+    /// the max-age of the window or at the oldest tracked item of the group,
+    /// none if never.
+    pub fn cutoff(&self, group: &str, window: Window, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let cutoff = now - window.max_age?;
+        Some(match self.oldest.get(group) {
+            Some(&oldest) => oldest.min(cutoff),
+            None => cutoff,
+        })
+    }
+}
+
+#[async_trait]
+pub trait Source: Send + Sync {
+    /// The kind of items this source yields.
+    fn kind(&self) -> SourceKind;
+
+    /// Names the source in `sources`, reports, state and schedules.
+    fn name(&self) -> String {
+        self.kind().to_string()
+    }
+
+    /// Describes the items in report headers, e.g. "no-reply issues within 3d".
+    fn describe(&self) -> String;
+
+    /// Fetches the items which are still waiting for a reply, the tracked
+    /// ones past the window too. Parts which fail are skipped and listed in
+    /// the failures, an error means nothing could be checked.
+    async fn fetch(&self, tracked: &Tracked) -> Result<Fetched>;
+}
+
+/// Where a notifier delivered a report, and the parts it could not deliver.
+pub struct Delivery {
+    /// e.g. "slack #channel", none if nothing was delivered
+    pub destination: Option<String>,
+    /// e.g. the users a direct message could not be sent to
+    pub failures: Vec<Failure>,
+}
+
+impl Delivery {
+    /// A report delivered as a whole.
+    pub fn to(destination: String) -> Self {
+        Delivery {
+            destination: Some(destination),
+            failures: vec![],
+        }
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Names the destination in logs, e.g. "slack #channel".
+    fn name(&self) -> String;
+
+    /// Delivers the report and returns where it went. An error means none
+    /// of it was delivered.
+    async fn send(&self, report: &Report) -> Result<Delivery>;
+}
+
+/// Builds the sources listed by `sources` in config.
+/// GitHub instances are listed by their names, their pull requests by the
+/// names with "-pulls", e.g. "github-pulls", and their stale review requests
+/// by the names with "-reviews". Every GitHub instance checked needs either
+/// a token or an app.
+pub fn from_config(conf: &Config) -> Result<Vec<Box<dyn Source>>> {
+    let instances = conf.github_instances();
+    let mut sources: Vec<Box<dyn Source>> = vec![];
+    // the sources of an instance share its app, so its installations and
+    // tokens are requested once
+    let mut apps: HashMap<String, Arc<App>> = HashMap::new();
+    // `max-concurrent-requests` bounds each host, whichever sources request it
+    let mut hosts: HashMap<String, Arc<Semaphore>> = HashMap::new();
+    let mut permits_of = |url: &str| {
+        hosts
+            .entry(host_of(url))
+            .or_insert_with(|| Arc::new(Semaphore::new(conf.max_concurrent_requests.max(1))))
+            .clone()
+    };
+    for name in &conf.sources {
+        if name == "discourse" {
+            sources.push(Box::new(Discourse::new(
+                conf.discourse_base_url.to_owned(),
+                conf.discourse_categories.clone(),
+                conf.discourse_members.clone(),
+                conf.window,
+                conf.category_windows.clone(),
+                permits_of(&conf.discourse_base_url),
+            )));
+            continue;
+        }
+        let find = |name: &str| instances.iter().find(|instance| instance.name == name);
+        // "" for the issues, or the suffix of what else is checked
+        let (instance, suffix) = match find(name) {
+            Some(instance) => (instance, ""),
+            None => ["-pulls", "-reviews"]
+                .iter()
+                .find_map(|suffix| Some((name.strip_suffix(suffix).and_then(find)?, *suffix)))
+                .ok_or_else(|| format!("unknown source {}", name))?,
+        };
+        // pull requests and reviews are cached apart, so none prunes the others
+        let cache_file = if !suffix.is_empty() && !instance.cache_file.is_empty() {
+            suffixed_cache_file(&instance.cache_file, suffix)
+        } else {
+            instance.cache_file.to_owned()
+        };
+        let auth = match &instance.app {
+            Some(_) if !instance.token.is_empty() => {
+                return Err(format!(
+                    "GitHub instance {} sets both a token and an app, set only one",
+                    instance.name
+                )
+                .into());
+            }
+            None if instance.token.is_empty() => {
+                return Err(
+                    format!("GitHub instance {} needs a token or an app", instance.name).into(),
+                );
+            }
+            Some(app) => match apps.get(&instance.name) {
+                Some(shared) => Auth::App(shared.clone()),
+                None => {
+                    let key = fs::read(&app.private_key_file)
+                        .with_context(|| format!("reading private key {}", app.private_key_file))?;
+                    let shared = Arc::new(App::new(app.app_id, &key).with_context(|| {
+                        format!("loading private key {}", app.private_key_file)
+                    })?);
+                    apps.insert(instance.name.to_owned(), shared.clone());
+                    Auth::App(shared)
+                }
+            },
+            None => Auth::Token(instance.token.to_owned()),
+        };
+        let github = GitHub::new(
+            instance,
+            auth,
+            conf.repo_rules_of(instance),
+            instance
+                .filter_labels
+                .clone()
+                .unwrap_or_else(|| conf.filter_labels.clone()),
+            // review requests are stale by `stale-review-after` instead
+            if suffix == "-reviews" {
+                Window::default()
+            } else {
+                Window {
+                    min_age: conf.window.min_age,
+                    // bounds the items seen first, so a first run does not
+                    // list every open issue, tracked items are checked on
+                    max_age: conf.window.max_age.or(Some(Duration::days(3))),
+                }
+            },
+            if cache_file.is_empty() {
+                None
+            } else {
+                Some(
+                    Cache::open(cache_file.to_owned())
+                        .with_context(|| format!("opening GitHub cache {}", cache_file))?,
+                )
+            },
+            permits_of(&instance.api_base_url),
+        )?;
+        match suffix {
+            "-pulls" => sources.push(Box::new(PullRequests::new(github))),
+            "-reviews" => sources.push(Box::new(ReviewRequests::new(
+                github,
+                conf.stale_review_after,
+            ))),
+            _ => sources.push(Box::new(github)),
+        }
+    }
+    Ok(sources)
+}
+
+/// Returns the host of a url, or the url itself if it has none.
+fn host_of(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed.host_str().unwrap_or(url).to_lowercase(),
+        Err(_) => url.to_owned(),
+    }
+}
+
+/// Inserts the suffix before the extension, e.g. "pingbot-github-cache.json"
+/// becomes "pingbot-github-cache-pulls.json".
+fn suffixed_cache_file(path: &str, suffix: &str) -> String {
+    match path.rfind('.') {
+        Some(dot) if !path[dot..].contains('/') => {
+            format!("{}{}{}", &path[..dot], suffix, &path[dot..])
+        }
+        _ => format!("{}{}", path, suffix),
+    }
+}
+
+/// Builds the notifiers listed by `notifiers` in config. Without any, reports
+/// go to the flat `slack-channel` if Slack is configured, else to stdout.
+/// Only the first Slack notifier posts routed reports to their channels, so
+/// none is posted twice.
+pub fn notifiers_from_config(conf: &Config, templates: Arc<Templates>) -> Vec<Box<dyn Notifier>> {
+    if conf.notifiers.is_empty() {
+        if !conf.slack_token.is_empty() && !conf.slack_channel.is_empty() {
+            return vec![Box::new(Slack::new(
+                conf.slack_token.to_owned(),
+                conf.slack_channel.to_owned(),
+                false,
+                true,
+                templates,
+            ))];
+        }
+        return vec![Box::new(StdoutNotifier::new(templates))];
+    }
+
+    let mut routed = false;
+    conf.notifiers
+        .iter()
+        .map(|notifier| -> Box<dyn Notifier> {
+            match notifier {
+                NotifierConfig::Slack {
+                    token,
+                    channel,
+                    blocks,
+                } => {
+                    let first = !routed;
+                    routed = true;
+                    Box::new(Slack::new(
+                        token.as_ref().unwrap_or(&conf.slack_token).to_owned(),
+                        channel.to_owned(),
+                        *blocks,
+                        first,
+                        templates.clone(),
+                    ))
+                }
+                NotifierConfig::SlackReviewers { token, users } => Box::new(SlackReviewers::new(
+                    token.as_ref().unwrap_or(&conf.slack_token).to_owned(),
+                    users.clone(),
+                    templates.clone(),
+                )),
+                NotifierConfig::Stdout => Box::new(StdoutNotifier::new(templates.clone())),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GitHubApp;
+
+    #[test]
+    fn tracked() {
+        let now = Utc::now();
+        let old = now - Duration::days(5);
+        let tracked = Tracked::new(vec![("u1", "o/r", old), ("u2", "o/r", now)]);
+        let window = Window {
+            min_age: None,
+            max_age: Some(Duration::days(3)),
+        };
+        assert!(tracked.admits(window, "u1", old, now));
+        assert!(!tracked.admits(window, "u3", old, now));
+        assert_eq!(tracked.cutoff("o/r", window, now), Some(old));
+        assert_eq!(
+            tracked.cutoff("o/x", window, now),
+            Some(now - Duration::days(3))
+        );
+        assert_eq!(tracked.cutoff("o/r", Window::default(), now), None);
+    }
+
+    #[test]
+    fn token_or_app() {
+        let mut conf = Config::new("config.example.toml".to_owned()).unwrap();
+        conf.sources = vec!["github".to_owned()];
+        conf.github_cache_file = "".to_owned();
+        assert!(from_config(&conf).is_ok());
+        conf.github_app = Some(GitHubApp {
+            app_id: 1,
+            private_key_file: "pingbot.private-key.pem".to_owned(),
+        });
+        assert!(from_config(&conf).is_err());
+        conf.github_token = "".to_owned();
+        conf.github_app = None;
+        assert!(from_config(&conf).is_err());
+    }
+}
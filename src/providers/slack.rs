@@ -1,54 +1,33 @@
-use std::fmt;
+use std::{collections::HashMap, sync::Arc};
 
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use reqwest;
 use serde::{Deserialize, Serialize};
-use serde_json;
+use serde_json::{self, json, Value};
 
-const API_BASE_URL: &str = "https://slack.com/api";
-
-pub type Result<T> = std::result::Result<T, Error>;
-
-#[derive(Debug)]
-pub struct Error {
-    reason: String,
-}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.reason)
-    }
-}
-
-impl std::error::Error for Error {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        None
-    }
-}
-
-impl From<reqwest::Error> for Error {
-    fn from(err: reqwest::Error) -> Self {
-        Error {
-            reason: err.to_string(),
-        }
-    }
-}
+use super::api::{self, ApiError};
+use super::{Delivery, Failure, Notifier};
+use crate::error::{Context, Result};
+use crate::report::{format_age, Entry, Report, Section};
+use crate::template::{escape_mrkdwn, Templates};
 
-impl From<serde_json::error::Error> for Error {
-    fn from(err: serde_json::error::Error) -> Self {
-        Error {
-            reason: err.to_string(),
-        }
-    }
-}
-
-impl From<String> for Error {
-    fn from(err: String) -> Self {
-        Error { reason: err }
-    }
-}
+const API_BASE_URL: &str = "https://slack.com/api";
+/// Slack rejects messages with more blocks than this.
+const MAX_BLOCKS: usize = 50;
+/// Slack rejects the text of a section longer than this, in characters.
+const MAX_TEXT_LEN: usize = 3000;
+/// Slack rejects the text of a header longer than this, in characters.
+const MAX_HEADER_LEN: usize = 150;
 
 pub struct Slack {
     token: String,
+    channel: String,
+    /// send Block Kit messages instead of plain text
+    blocks: bool,
+    /// post reports routed to a channel there instead of `channel`
+    routed: bool,
+    templates: Arc<Templates>,
     client: reqwest::Client,
 }
 
@@ -57,10 +36,20 @@ struct Header {
     value: String,
 }
 
+/// Reminds the requested reviewers of pull requests in direct messages, each
+/// with only the items waiting for them.
+pub struct SlackReviewers {
+    slack: Slack,
+    /// Slack user ids by GitHub login or "org/team"
+    users: HashMap<String, String>,
+}
+
 #[derive(Serialize)]
 struct Message {
     text: String,
     channel: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blocks: Option<Value>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -70,11 +59,21 @@ struct Response {
 }
 
 impl Slack {
-    pub fn new(token: String) -> Self {
+    pub fn new(
+        token: String,
+        channel: String,
+        blocks: bool,
+        routed: bool,
+        templates: Arc<Templates>,
+    ) -> Self {
         let mut auth_header = "Bearer ".to_owned();
         auth_header.push_str(&token);
         Slack {
             token: auth_header,
+            channel,
+            blocks,
+            routed,
+            templates,
             client: reqwest::Client::new(),
         }
     }
@@ -89,22 +88,484 @@ impl Slack {
         for header in headers {
             req = req.header(&header.key[..], &header.value[..]);
         }
-        let res = req.body(body).send().await?.text().await?;
-        Ok(res)
+        let res = req.body(body).send().await?;
+        let status = res.status();
+        let body = res.text().await?;
+        api::check(url, status, &body)?;
+        Ok(body)
     }
 
     pub async fn send_message(&self, channel: String, text: String) -> Result<()> {
+        self.post_message(Message {
+            text,
+            channel,
+            blocks: None,
+        })
+        .await
+    }
+
+    /// Sends a Block Kit message, `text` is shown in notifications.
+    pub async fn send_blocks(&self, channel: String, text: String, blocks: Value) -> Result<()> {
+        self.post_message(Message {
+            text,
+            channel,
+            blocks: Some(blocks),
+        })
+        .await
+    }
+
+    async fn post_message(&self, message: Message) -> Result<()> {
         let url = format!("{}/{}", API_BASE_URL, "chat.postMessage");
-        let message = Message { text, channel };
         let body = serde_json::to_string(&message)?;
         let res_text = self.request(&url[..], vec![], body).await?;
-        let res: Response = serde_json::from_str(&res_text[..])?;
+        let res: Response = api::decode(&url, &res_text)?;
         match res.ok {
             true => Ok(()),
-            false => match res.error {
-                Some(e) => Err(e.into()),
-                None => Err("unknown error".to_owned().into()),
+            false => {
+                Err(rejected(url, res.error.unwrap_or_else(|| "unknown error".to_owned())).into())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Slack {
+    fn name(&self) -> String {
+        format!("slack {}", self.channel)
+    }
+
+    async fn send(&self, report: &Report) -> Result<Delivery> {
+        let channel = match &report.channel {
+            Some(channel) if self.routed => channel,
+            _ => &self.channel,
+        };
+        if self.blocks {
+            let text = format!("{} items waiting for a reply", report.len());
+            self.send_blocks(channel.to_owned(), text, render_blocks(report))
+                .await
+                .with_context(|| format!("posting to {}", channel))?;
+        } else {
+            let text = self
+                .templates
+                .render_mrkdwn(report)
+                .context("rendering the report")?;
+            self.send_message(channel.to_owned(), text)
+                .await
+                .with_context(|| format!("posting to {}", channel))?;
+        }
+        Ok(Delivery::to(format!("slack {}", channel)))
+    }
+}
+
+impl SlackReviewers {
+    pub fn new(token: String, users: HashMap<String, String>, templates: Arc<Templates>) -> Self {
+        SlackReviewers {
+            // messages are sent to the users instead of a channel
+            slack: Slack::new(token, String::new(), false, false, templates),
+            // reviewers are looked up case-insensitively
+            users: users
+                .into_iter()
+                .map(|(name, user)| (name.to_lowercase(), user))
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for SlackReviewers {
+    fn name(&self) -> String {
+        "slack reviewers".to_owned()
+    }
+
+    async fn send(&self, report: &Report) -> Result<Delivery> {
+        let reports = split_by_reviewer(report, &self.users);
+        // a user who cannot be messaged does not keep the others from it
+        let mut failures = vec![];
+        for (user, report) in &reports {
+            let text = self
+                .slack
+                .templates
+                .render_mrkdwn(report)
+                .context("rendering the report")?;
+            // posting to a user id sends a direct message from the app
+            if let Err(e) = self.slack.send_message(user.to_owned(), text).await {
+                failures.push(Failure {
+                    target: format!("slack {}", user),
+                    reason: e.to_string(),
+                });
+            }
+        }
+        let sent = reports.len() - failures.len();
+        Ok(Delivery {
+            destination: if sent == 0 {
+                None
+            } else {
+                Some(format!("slack reviewers ({} users)", sent))
             },
+            failures,
+        })
+    }
+}
+
+/// Splits the stale review requests of a report by the Slack users of their
+/// reviewers, in the order they are first requested. `users` are keyed by
+/// lowercase names, the reviewers without a user are skipped. Other sections,
+/// e.g. the pull requests of "-pulls" sources, are left out, as they are not
+/// stale by `stale-review-after`.
+fn split_by_reviewer(report: &Report, users: &HashMap<String, String>) -> Vec<(String, Report)> {
+    let mut reports: Vec<(String, Report)> = vec![];
+    for section in report
+        .sections
+        .iter()
+        .filter(|section| section.source.ends_with("-reviews"))
+    {
+        for entry in &section.entries {
+            let mut notified = vec![];
+            for reviewer in &entry.item.reviewers {
+                let user = match users.get(&reviewer.to_lowercase()) {
+                    Some(user) => user,
+                    None => {
+                        println!("No Slack user of reviewer {}", reviewer);
+                        continue;
+                    }
+                };
+                // a user may be requested both in person and by team
+                if notified.contains(&user) {
+                    continue;
+                }
+                notified.push(user);
+                let index = match reports.iter().position(|(u, _)| u == user) {
+                    Some(index) => index,
+                    None => {
+                        let mut report = Report::new();
+                        report.channel = Some(user.to_owned());
+                        reports.push((user.to_owned(), report));
+                        reports.len() - 1
+                    }
+                };
+                let sections = &mut reports[index].1.sections;
+                match sections.iter_mut().find(|s| s.source == section.source) {
+                    Some(s) => s.entries.push(entry.clone()),
+                    None => sections.push(Section {
+                        source: section.source.to_owned(),
+                        description: section.description.to_owned(),
+                        entries: vec![entry.clone()],
+                        resolved: vec![],
+                    }),
+                }
+            }
+        }
+    }
+    reports
+}
+
+/// Classifies the `error` of a response with `ok: false`.
+fn rejected(url: String, error: String) -> ApiError {
+    match &error[..] {
+        "not_authed" | "invalid_auth" | "account_inactive" | "token_revoked" | "missing_scope" => {
+            ApiError::Auth {
+                url,
+                message: error,
+            }
+        }
+        "channel_not_found" | "user_not_found" => ApiError::NotFound {
+            url,
+            message: error,
+        },
+        "ratelimited" => ApiError::RateLimited {
+            url,
+            message: error,
+        },
+        _ => ApiError::Rejected {
+            url,
+            message: error,
+        },
+    }
+}
+
+/// Renders the report as Block Kit blocks: a header per source, a section per
+/// item and a context footer with the counts. Blocks which would not fit in
+/// `MAX_BLOCKS` are left out, room for the failures and footer is kept.
+fn render_blocks(report: &Report) -> Value {
+    let now = Utc::now();
+    let mut blocks = vec![];
+    let mut shown = 0;
+    // room for the failures and the footer
+    let reserved = if report.failures.is_empty() { 1 } else { 2 };
+    let fits = |blocks: &Vec<Value>, n: usize| blocks.len() + n + reserved <= MAX_BLOCKS;
+    for section in &report.sections {
+        // a header only with room for an item under it
+        if !section.entries.is_empty() && fits(&blocks, 2) {
+            blocks.push(json!({
+                "type": "header",
+                "text": {
+                    "type": "plain_text",
+                    "text": truncate(
+                        &format!("{} {}", section.entries.len(), section.description),
+                        MAX_HEADER_LEN
+                    ),
+                },
+            }));
+            for entry in &section.entries {
+                if !fits(&blocks, 1) {
+                    break;
+                }
+                blocks.push(mrkdwn_section(&render_entry(entry, now)));
+                shown += 1;
+            }
+        }
+        if !section.resolved.is_empty() && fits(&blocks, 1) {
+            let lines: Vec<String> = section
+                .resolved
+                .iter()
+                .map(|(url, record)| format!("~<{}|{}>~", url, escape_mrkdwn(&record.title)))
+                .collect();
+            blocks.push(mrkdwn_section(&format!(
+                "*{} resolved since last report*\n{}",
+                lines.len(),
+                lines.join("\n")
+            )));
         }
     }
+
+    if !report.failures.is_empty() {
+        let lines: Vec<String> = report
+            .failures
+            .iter()
+            .map(|failure| {
+                format!(
+                    "{} ({})",
+                    escape_mrkdwn(&failure.target),
+                    escape_mrkdwn(&failure.reason)
+                )
+            })
+            .collect();
+        blocks.push(mrkdwn_section(&format!(
+            "*Could not check*\n{}",
+            lines.join("\n")
+        )));
+    }
+
+    let total = report.len();
+    let mut footer = format!(
+        "{} items waiting for a reply from {} sources",
+        total,
+        report.sections.len()
+    );
+    if shown < total {
+        footer.push_str(&format!(", {} not shown", total - shown));
+    }
+    blocks.push(json!({
+        "type": "context",
+        "elements": [{ "type": "mrkdwn", "text": truncate(&footer, MAX_TEXT_LEN) }],
+    }));
+    Value::Array(blocks)
+}
+
+/// Renders the mrkdwn text of an item.
+fn render_entry(entry: &Entry, now: DateTime<Utc>) -> String {
+    let item = &entry.item;
+    let mut text = format!(
+        "*<{}|{}>*\n{} · by {} · opened {} ago",
+        item.url,
+        escape_mrkdwn(&item.title),
+        escape_mrkdwn(&item.group),
+        escape_mrkdwn(&item.author),
+        format_age(now.signed_duration_since(item.created_at))
+    );
+    if !item.tags.is_empty() {
+        let tags: Vec<String> = item
+            .tags
+            .iter()
+            .map(|tag| format!("`{}`", escape_mrkdwn(tag)))
+            .collect();
+        text.push_str(&format!(" · {}", tags.join(" ")));
+    }
+    if !item.reviewers.is_empty() {
+        text.push_str(&format!(
+            "\nreview requested from {}",
+            escape_mrkdwn(&item.reviewers.join(", "))
+        ));
+    }
+    if let Some(record) = &entry.record {
+        text.push_str(&format!(
+            "\n_reported {} times since {}_",
+            record.times,
+            record.first_reported.format("%Y-%m-%d")
+        ));
+    }
+    if !entry.mentions.is_empty() {
+        text.push_str(&format!("\n{}", entry.mentions.join(" ")));
+    }
+    text
+}
+
+fn mrkdwn_section(text: &str) -> Value {
+    json!({
+        "type": "section",
+        "text": { "type": "mrkdwn", "text": truncate(text, MAX_TEXT_LEN) },
+    })
+}
+
+/// Cuts the text to at most `max` characters, ending it with "…" if cut.
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_owned();
+    }
+    let mut cut: String = text.chars().take(max - 1).collect();
+    cut.push('…');
+    cut
+}
+
+/// Escapes the control characters of Slack mrkdwn.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{new_test_item, Failure, Item};
+    use crate::store::Record;
+    use chrono::Duration;
+
+    fn new_report(n: usize) -> Report {
+        let items = (0..n)
+            .map(|i| Item {
+                title: format!("<issue {}>", i),
+                tags: vec!["bug".to_owned()],
+                ..new_test_item(
+                    &format!("https://github.com/o/r/issues/{}", i),
+                    Utc::now() - Duration::hours(41),
+                )
+            })
+            .collect();
+        let mut report = Report::new();
+        report.push(Section::new(
+            "github".to_owned(),
+            "no-reply issues".to_owned(),
+            items,
+        ));
+        report
+    }
+
+    #[test]
+    fn blocks() {
+        let blocks = render_blocks(&new_report(2));
+        let blocks = blocks.as_array().unwrap();
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(blocks[0]["type"], "header");
+        assert_eq!(blocks[0]["text"]["text"], "2 no-reply issues");
+        assert_eq!(
+            blocks[1]["text"]["text"],
+            "*<https://github.com/o/r/issues/0|&lt;issue 0&gt;>*\no/r · by you06 · opened 41h ago · `bug`"
+        );
+        assert_eq!(blocks[3]["type"], "context");
+
+        let blocks = render_blocks(&new_report(100));
+        let blocks = blocks.as_array().unwrap();
+        assert_eq!(blocks.len(), MAX_BLOCKS);
+        assert_eq!(
+            blocks[blocks.len() - 1]["elements"][0]["text"],
+            "100 items waiting for a reply from 1 sources, 52 not shown"
+        );
+
+        let mut report = new_report(100);
+        report.failures = vec![Failure {
+            target: "o/x".to_owned(),
+            reason: "not found".to_owned(),
+        }];
+        let blocks = render_blocks(&report);
+        let blocks = blocks.as_array().unwrap();
+        assert_eq!(blocks.len(), MAX_BLOCKS);
+        assert_eq!(
+            blocks[blocks.len() - 2]["text"]["text"],
+            "*Could not check*\no/x (not found)"
+        );
+    }
+
+    #[test]
+    fn blocks_of_sections() {
+        let mut report = Report::new();
+        for source in &["github", "github-pulls", "discourse"] {
+            let mut section = new_report(30).sections.remove(0);
+            section.source = source.to_string();
+            section.entries[0].item.title = "x".repeat(5000);
+            section.resolved = vec![(
+                "https://github.com/o/r/issues/100".to_owned(),
+                Record {
+                    source: source.to_string(),
+                    title: "resolved".to_owned(),
+                    group: "o/r".to_owned(),
+                    created_at: None,
+                    first_reported: Utc::now(),
+                    last_reported: Utc::now(),
+                    times: 1,
+                    channels: vec![],
+                    resolved_at: None,
+                    expired_at: None,
+                },
+            )];
+            report.push(section);
+        }
+        report.failures = vec![Failure {
+            target: "o/x".to_owned(),
+            reason: "not found".to_owned(),
+        }];
+        let blocks = render_blocks(&report);
+        let blocks = blocks.as_array().unwrap();
+        assert_eq!(blocks.len(), MAX_BLOCKS);
+        // the second section starts after the 30 items and resolved block
+        assert_eq!(blocks[32]["type"], "header");
+        assert_eq!(
+            blocks[blocks.len() - 2]["text"]["text"],
+            "*Could not check*\no/x (not found)"
+        );
+        assert_eq!(
+            blocks[blocks.len() - 1]["elements"][0]["text"],
+            "90 items waiting for a reply from 3 sources, 45 not shown"
+        );
+        for block in blocks {
+            if let Some(text) = block["text"]["text"].as_str() {
+                assert!(text.chars().count() <= MAX_TEXT_LEN);
+            }
+        }
+        assert!(blocks[1]["text"]["text"].as_str().unwrap().ends_with('…'));
+    }
+
+    #[test]
+    fn reviewers() {
+        let mut report = new_report(3);
+        report.sections[0].source = "github-reviews".to_owned();
+        let mut pulls = new_report(1).sections.remove(0);
+        pulls.source = "github-pulls".to_owned();
+        pulls.entries[0].item.reviewers = vec!["a".to_owned()];
+        report.push(pulls);
+        let entries = &mut report.sections[0].entries;
+        entries[0].item.reviewers = vec!["a".to_owned(), "o/team".to_owned()];
+        entries[1].item.reviewers = vec!["B".to_owned(), "nobody".to_owned()];
+        entries[2].item.reviewers = vec!["o/team".to_owned()];
+        let users: HashMap<String, String> = vec![("a", "U1"), ("b", "U2"), ("o/team", "U1")]
+            .into_iter()
+            .map(|(name, user)| (name.to_owned(), user.to_owned()))
+            .collect();
+        let reports = split_by_reviewer(&report, &users);
+        let urls = |report: &Report| -> Vec<String> {
+            report.sections[0]
+                .entries
+                .iter()
+                .map(|entry| entry.item.url.to_owned())
+                .collect()
+        };
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].0, "U1");
+        assert_eq!(reports[0].1.sections.len(), 1);
+        assert_eq!(reports[0].1.channel, Some("U1".to_owned()));
+        assert_eq!(
+            urls(&reports[0].1),
+            vec![
+                "https://github.com/o/r/issues/0",
+                "https://github.com/o/r/issues/2"
+            ]
+        );
+        assert_eq!(reports[1].0, "U2");
+        assert_eq!(urls(&reports[1].1), vec!["https://github.com/o/r/issues/1"]);
+        assert_eq!(reports[1].1.sections[0].description, "no-reply issues");
+    }
 }
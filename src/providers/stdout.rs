@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use super::{Delivery, Notifier};
+use crate::error::Result;
+use crate::report::Report;
+use crate::template::Templates;
+
+/// Prints reports to stdout, used when no other notifier is configured.
+pub struct StdoutNotifier {
+    templates: Arc<Templates>,
+}
+
+impl StdoutNotifier {
+    pub fn new(templates: Arc<Templates>) -> Self {
+        StdoutNotifier { templates }
+    }
+}
+
+#[async_trait]
+impl Notifier for StdoutNotifier {
+    fn name(&self) -> String {
+        "stdout".to_owned()
+    }
+
+    async fn send(&self, report: &Report) -> Result<Delivery> {
+        if let Some(channel) = &report.channel {
+            println!("To {}:", channel);
+        }
+        println!("{}", self.templates.render(report)?);
+        Ok(Delivery::to(self.name()))
+    }
+}
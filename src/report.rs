@@ -0,0 +1,134 @@
+use chrono::Duration;
+
+use crate::providers::{Failure, Item};
+use crate::store::Record;
+
+/// An item in a report, with what the store remembers about it.
+#[derive(Clone)]
+pub struct Entry {
+    pub item: Item,
+    pub record: Option<Record>,
+    /// mentions of the escalation tier the item reached
+    pub mentions: Vec<String>,
+}
+
+/// Items found by one source.
+pub struct Section {
+    pub source: String,
+    pub description: String,
+    pub entries: Vec<Entry>,
+    /// items reported before which are no longer waiting, with their urls
+    pub resolved: Vec<(String, Record)>,
+}
+
+/// All sections found in one run, in source order.
+pub struct Report {
+    /// overrides the channel of notifiers
+    pub channel: Option<String>,
+    pub sections: Vec<Section>,
+    /// what could not be checked, so the report may be incomplete
+    pub failures: Vec<Failure>,
+}
+
+impl Section {
+    pub fn new(source: String, description: String, items: Vec<Item>) -> Self {
+        Section {
+            source,
+            description,
+            entries: items
+                .into_iter()
+                .map(|item| Entry {
+                    item,
+                    record: None,
+                    mentions: vec![],
+                })
+                .collect(),
+            resolved: vec![],
+        }
+    }
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Report {
+            channel: None,
+            sections: vec![],
+            failures: vec![],
+        }
+    }
+
+    pub fn push(&mut self, section: Section) {
+        if !section.entries.is_empty() || !section.resolved.is_empty() {
+            self.sections.push(section);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty() && self.failures.is_empty()
+    }
+
+    /// Returns the number of items waiting for a reply.
+    pub fn len(&self) -> usize {
+        self.sections
+            .iter()
+            .map(|section| section.entries.len())
+            .sum()
+    }
+
+    /// Splits the report by the channels of its items. Items without a
+    /// channel, resolved items and failures stay in the first report.
+    pub fn split_by_channel(self) -> Vec<Report> {
+        let mut reports = vec![Report::new()];
+        reports[0].failures = self.failures;
+        for section in self.sections {
+            let mut split = vec![(
+                None,
+                Section {
+                    source: section.source.to_owned(),
+                    description: section.description.to_owned(),
+                    entries: vec![],
+                    resolved: section.resolved,
+                },
+            )];
+            for entry in section.entries {
+                let channel = entry.item.channel.clone();
+                match split.iter_mut().find(|(c, _)| *c == channel) {
+                    Some((_, s)) => s.entries.push(entry),
+                    None => split.push((
+                        channel,
+                        Section {
+                            source: section.source.to_owned(),
+                            description: section.description.to_owned(),
+                            entries: vec![entry],
+                            resolved: vec![],
+                        },
+                    )),
+                }
+            }
+            for (channel, section) in split {
+                match reports.iter_mut().find(|r| r.channel == channel) {
+                    Some(report) => report.push(section),
+                    None => {
+                        let mut report = Report::new();
+                        report.channel = channel;
+                        report.push(section);
+                        reports.push(report);
+                    }
+                }
+            }
+        }
+        reports.retain(|report| !report.is_empty());
+        reports
+    }
+}
+
+/// Formats the age of an item coarsely, e.g. "45m", "41h" or "5d".
+pub fn format_age(age: Duration) -> String {
+    if age < Duration::hours(1) {
+        format!("{}m", age.num_minutes())
+    } else if age < Duration::hours(48) {
+        format!("{}h", age.num_hours())
+    } else {
+        format!("{}d", age.num_days())
+    }
+}
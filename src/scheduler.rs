@@ -0,0 +1,72 @@
+use std::{future::Future, sync::Arc};
+
+use chrono::Utc;
+use cron::Schedule;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::broadcast,
+    time::delay_for,
+};
+
+use crate::error::Result;
+
+/// A task which runs on its own cron schedule.
+pub struct Job<T: ?Sized> {
+    pub name: String,
+    pub schedule: Schedule,
+    pub task: Arc<T>,
+}
+
+/// Runs every job on its schedule until SIGTERM or Ctrl-C.
+///
+/// A job never overlaps with itself: the next run is scheduled only after the
+/// current one finishes, and ticks missed meanwhile are skipped. On shutdown,
+/// running jobs are allowed to finish.
+pub async fn run<T, F, Fut>(jobs: Vec<Job<T>>, f: F) -> Result<()>
+where
+    T: ?Sized + Send + Sync + 'static,
+    F: Fn(Arc<T>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send,
+{
+    let (shutdown, _) = broadcast::channel::<()>(1);
+    let mut handles = vec![];
+    for job in jobs {
+        let mut stop = shutdown.subscribe();
+        let f = f.clone();
+        handles.push(tokio::spawn(async move {
+            while let Some(next) = job.schedule.upcoming(Utc).next() {
+                println!("Next {} check at {}", job.name, next);
+                let wait = (next - Utc::now()).to_std().unwrap_or_default();
+                tokio::select! {
+                    _ = delay_for(wait) => {}
+                    _ = stop.recv() => break,
+                }
+
+                let started = Utc::now();
+                if let Err(e) = f(job.task.clone()).await {
+                    println!("Check {} failed: {}", job.name, e);
+                }
+                if let Some(missed) = job.schedule.after(&started).next() {
+                    if missed < Utc::now() {
+                        println!("Check {} ran past {}, skipping", job.name, missed);
+                    }
+                }
+            }
+            println!("Stopped {}", job.name);
+        }));
+    }
+
+    let mut term = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = term.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    println!("Shutting down, waiting for running checks");
+    // jobs which already stopped have dropped their receivers
+    let _ = shutdown.send(());
+
+    for handle in handles {
+        handle.await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Error,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::json_file;
+use crate::providers::{Item, Tracked};
+
+/// What pingbot remembers about a reported item.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Record {
+    pub source: String,
+    pub title: String,
+    /// the repo or category of the item, missing in older state files
+    #[serde(default)]
+    pub group: String,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    pub first_reported: DateTime<Utc>,
+    pub last_reported: DateTime<Utc>,
+    pub times: u32,
+    pub channels: Vec<String>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    /// set when the item left the window or was filtered out unanswered
+    #[serde(default)]
+    pub expired_at: Option<DateTime<Utc>>,
+}
+
+impl Record {
+    fn is_open(&self) -> bool {
+        self.resolved_at.is_none() && self.expired_at.is_none()
+    }
+}
+
+/// Reported items keyed by source and url, persisted as a JSON file between
+/// runs. An item reported by several sources, e.g. a pull request waiting
+/// for a first review and for a requested reviewer, has a record for each.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Store {
+    #[serde(skip)]
+    path: String,
+    records: HashMap<String, Record>,
+}
+
+/// Returns the key of the record of an item, urls have no spaces.
+fn key(source: &str, url: &str) -> String {
+    format!("{} {}", source, url)
+}
+
+impl Store {
+    pub fn open(path: String) -> Result<Self, Error> {
+        let mut store: Store = json_file::load(&path)?;
+        // older state files keyed records by url only
+        store.records = store
+            .records
+            .into_iter()
+            .map(|(url, record)| {
+                if url.contains(' ') {
+                    (url, record)
+                } else {
+                    (key(&record.source, &url), record)
+                }
+            })
+            .collect();
+        store.path = path;
+        Ok(store)
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        json_file::save(&self.path, self)
+    }
+
+    /// Returns the record of an item which is still waiting for a reply.
+    pub fn get(&self, source: &str, url: &str) -> Option<&Record> {
+        self.records
+            .get(&key(source, url))
+            .filter(|record| record.is_open())
+    }
+
+    /// Records that the item was sent to the channels.
+    pub fn reported(&mut self, source: &str, item: &Item, channels: &[String], now: DateTime<Utc>) {
+        let record = self
+            .records
+            .entry(key(source, &item.url))
+            .or_insert_with(|| Record {
+                source: source.to_owned(),
+                title: item.title.to_owned(),
+                group: item.group.to_owned(),
+                created_at: Some(item.created_at),
+                first_reported: now,
+                last_reported: now,
+                times: 0,
+                channels: vec![],
+                resolved_at: None,
+                expired_at: None,
+            });
+        if !record.is_open() {
+            // it is waiting again, e.g. reopened
+            record.first_reported = now;
+            record.times = 0;
+            record.resolved_at = None;
+            record.expired_at = None;
+        }
+        record.group = item.group.to_owned();
+        record.created_at = Some(item.created_at);
+        record.last_reported = now;
+        record.times += 1;
+        for channel in channels {
+            if !record.channels.contains(channel) {
+                record.channels.push(channel.to_owned());
+            }
+        }
+    }
+
+    /// Returns the open items of the source, which it keeps checking.
+    pub fn tracked(&self, source: &str) -> Tracked {
+        let prefix = key(source, "");
+        Tracked::new(
+            self.records
+                .iter()
+                .filter(|(_, record)| record.source == source && record.is_open())
+                .filter_map(|(key, record)| {
+                    let created_at = record.created_at.unwrap_or(record.first_reported);
+                    Some((
+                        key.strip_prefix(&prefix[..])?,
+                        &record.group[..],
+                        created_at,
+                    ))
+                }),
+        )
+    }
+
+    /// Returns the open items of the source which are no longer waiting.
+    pub fn unresolved(&self, source: &str, waiting: &HashSet<String>) -> Vec<(String, Record)> {
+        let prefix = key(source, "");
+        let mut unresolved: Vec<(String, Record)> = self
+            .records
+            .iter()
+            .filter(|(_, record)| record.source == source && record.is_open())
+            .filter_map(|(key, record)| Some((key.strip_prefix(&prefix[..])?, record)))
+            .filter(|(url, _)| !waiting.contains(*url))
+            .map(|(url, record)| (url.to_owned(), record.clone()))
+            .collect();
+        unresolved.sort_by_key(|(_, record)| record.first_reported);
+        unresolved
+    }
+
+    pub fn resolve(&mut self, source: &str, url: &str, now: DateTime<Utc>) {
+        if let Some(record) = self.records.get_mut(&key(source, url)) {
+            record.resolved_at = Some(now);
+        }
+    }
+
+    /// Stops tracking an item which is no longer checked, without counting it
+    /// as resolved.
+    pub fn expire(&mut self, source: &str, url: &str, now: DateTime<Utc>) {
+        if let Some(record) = self.records.get_mut(&key(source, url)) {
+            record.expired_at = Some(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::new_test_item;
+
+    fn new_item(url: &str) -> Item {
+        new_test_item(url, Utc::now())
+    }
+
+    #[test]
+    fn report_and_resolve() {
+        let mut store = Store::default();
+        let now = Utc::now();
+        let channels = vec!["slack #ch".to_owned()];
+        store.reported("github", &new_item("a"), &channels, now);
+        store.reported("github", &new_item("b"), &channels, now);
+        store.reported("github", &new_item("b"), &channels, now);
+        assert_eq!(store.get("github", "a").unwrap().times, 1);
+        assert_eq!(store.get("github", "b").unwrap().times, 2);
+        assert_eq!(store.get("github", "b").unwrap().channels, channels);
+
+        let waiting = vec!["b".to_owned()].into_iter().collect();
+        let unresolved = store.unresolved("github", &waiting);
+        assert_eq!(unresolved.len(), 1);
+        assert_eq!(unresolved[0].0, "a");
+        assert!(store.unresolved("discourse", &HashSet::new()).is_empty());
+        store.resolve("github", "a", now);
+        assert!(store.get("github", "a").is_none());
+        assert!(store.unresolved("github", &waiting).is_empty());
+
+        // reported again after resolved
+        store.reported("github", &new_item("a"), &channels, now);
+        assert_eq!(store.get("github", "a").unwrap().times, 1);
+
+        store.expire("github", "b", now);
+        assert!(store.get("github", "b").is_none());
+        assert!(store.unresolved("github", &HashSet::new()).len() == 1);
+        store.reported("github", &new_item("b"), &channels, now);
+        assert_eq!(store.get("github", "b").unwrap().times, 1);
+        assert_eq!(store.get("github", "b").unwrap().group, "o/r");
+    }
+
+    #[test]
+    fn old_state_file() {
+        let path = std::env::temp_dir().join("pingbot-store-old.json");
+        let now = Utc::now().to_rfc3339();
+        let record = format!(
+            r#"{{"source": "github", "title": "t", "first_reported": "{0}",
+            "last_reported": "{0}", "times": 1, "channels": [], "resolved_at": null}}"#,
+            now
+        );
+        std::fs::write(&path, format!(r#"{{"records": {{"a": {}}}}}"#, record)).unwrap();
+        let store = Store::open(path.to_string_lossy().into_owned()).unwrap();
+        assert_eq!(store.get("github", "a").unwrap().times, 1);
+        assert!(store.get("github-pulls", "a").is_none());
+        assert_eq!(store.unresolved("github", &HashSet::new())[0].0, "a");
+    }
+}
@@ -0,0 +1,392 @@
+use std::fs::read_to_string;
+
+use chrono::{DateTime, Utc};
+use handlebars::{no_escape, Handlebars, RenderError};
+use serde::Serialize;
+
+use crate::config::TemplateFiles;
+use crate::error::{Context, Result};
+use crate::report::{format_age, Entry, Report, Section};
+
+/// One line per item, e.g. "title https://... opened 41h ago by you06 [bug]".
+pub const DEFAULT_ITEM_TEMPLATE: &str = "{{title}} {{url}} opened {{age}} ago by {{author}}\
+{{#if labels}} [{{#each labels}}{{this}}{{#unless @last}}, {{/unless}}{{/each}}]{{/if}}\
+{{#if reviewers}} (review requested from \
+{{#each reviewers}}{{this}}{{#unless @last}}, {{/unless}}{{/each}}){{/if}}\
+{{#if reported}} (reported {{reported}} times since {{first_reported}}){{/if}}\
+{{#each mentions}} {{{this}}}{{/each}}";
+
+/// A section per source, `items` are rendered by the item template as `line`.
+pub const DEFAULT_SECTION_TEMPLATE: &str = "{{#if items}}{{count}} {{description}}\n\
+{{#each items}}{{{line}}}\n{{/each}}{{/if}}\
+{{#if resolved}}{{resolved_count}} resolved since last report\n\
+{{#each resolved}}{{title}}, {{url}}\n{{/each}}{{/if}}";
+
+/// Sections rendered by the section template as `text`, separated by blank
+/// lines, then what could not be checked.
+pub const DEFAULT_REPORT_TEMPLATE: &str =
+    "{{#each sections}}{{#unless @first}}\n\n{{/unless}}{{{text}}}{{/each}}\
+{{#if failures}}{{#if sections}}\n\n{{/if}}\
+{{#each failures}}could not check: {{target}} ({{reason}})\n{{/each}}{{/if}}";
+
+#[derive(Serialize)]
+struct ItemVars {
+    title: String,
+    url: String,
+    author: String,
+    /// e.g. "41h"
+    age: String,
+    created_at: String,
+    kind: String,
+    /// the repo of an issue or the category of a topic
+    group: String,
+    labels: Vec<String>,
+    /// pending reviewers of a pull request
+    reviewers: Vec<String>,
+    reported: Option<u32>,
+    first_reported: Option<String>,
+    mentions: Vec<String>,
+    /// the rendered item template
+    line: String,
+}
+
+#[derive(Serialize)]
+struct ResolvedVars {
+    title: String,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct SectionVars {
+    source: String,
+    description: String,
+    count: usize,
+    items: Vec<ItemVars>,
+    resolved_count: usize,
+    resolved: Vec<ResolvedVars>,
+    /// the rendered section template
+    text: String,
+}
+
+#[derive(Serialize)]
+struct FailureVars {
+    target: String,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct ReportVars {
+    channel: Option<String>,
+    count: usize,
+    sections: Vec<SectionVars>,
+    failures: Vec<FailureVars>,
+}
+
+/// Renders reports as text with Handlebars templates.
+pub struct Templates {
+    /// renders values as they are
+    registry: Handlebars<'static>,
+    /// escapes values for Slack mrkdwn
+    mrkdwn: Handlebars<'static>,
+}
+
+impl Templates {
+    /// Loads the template files, `item` is the inline item template which is
+    /// used if there is no item template file.
+    pub fn new(files: &TemplateFiles, item: Option<&str>) -> Result<Self> {
+        let item = match &files.item {
+            Some(path) => load(path)?,
+            None => item.unwrap_or(DEFAULT_ITEM_TEMPLATE).to_owned(),
+        };
+        let section = match &files.section {
+            Some(path) => load(path)?,
+            None => DEFAULT_SECTION_TEMPLATE.to_owned(),
+        };
+        let report = match &files.report {
+            Some(path) => load(path)?,
+            None => DEFAULT_REPORT_TEMPLATE.to_owned(),
+        };
+        let new_registry = |escape: fn(&str) -> String| -> Result<Handlebars<'static>> {
+            let mut registry = Handlebars::new();
+            registry.register_escape_fn(escape);
+            registry
+                .register_template_string("item", &item)
+                .context("parsing the item template")?;
+            registry
+                .register_template_string("section", &section)
+                .context("parsing the section template")?;
+            registry
+                .register_template_string("report", &report)
+                .context("parsing the report template")?;
+            Ok(registry)
+        };
+        Ok(Templates {
+            // reports are not HTML
+            registry: new_registry(no_escape)?,
+            mrkdwn: new_registry(escape_mrkdwn)?,
+        })
+    }
+
+    pub fn render(&self, report: &Report) -> std::result::Result<String, RenderError> {
+        render_with(&self.registry, report)
+    }
+
+    /// Renders a report for Slack, so titles and other values cannot mention
+    /// or link as markup.
+    pub fn render_mrkdwn(&self, report: &Report) -> std::result::Result<String, RenderError> {
+        render_with(&self.mrkdwn, report)
+    }
+}
+
+/// Escapes the characters Slack mrkdwn reads as markup.
+pub fn escape_mrkdwn(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn render_with(registry: &Handlebars, report: &Report) -> std::result::Result<String, RenderError> {
+    let now = Utc::now();
+    let mut sections = vec![];
+    for section in &report.sections {
+        sections.push(section_vars(registry, section, now)?);
+    }
+    let vars = ReportVars {
+        channel: report.channel.clone(),
+        count: report.len(),
+        sections,
+        failures: report
+            .failures
+            .iter()
+            .map(|failure| FailureVars {
+                target: failure.target.to_owned(),
+                reason: failure.reason.to_owned(),
+            })
+            .collect(),
+    };
+    registry.render("report", &vars)
+}
+
+fn section_vars(
+    registry: &Handlebars,
+    section: &Section,
+    now: DateTime<Utc>,
+) -> std::result::Result<SectionVars, RenderError> {
+    let mut items = vec![];
+    for entry in &section.entries {
+        let mut vars = item_vars(entry, now);
+        vars.line = registry.render("item", &vars)?;
+        items.push(vars);
+    }
+    let mut vars = SectionVars {
+        source: section.source.to_owned(),
+        description: section.description.to_owned(),
+        count: items.len(),
+        items,
+        resolved_count: section.resolved.len(),
+        resolved: section
+            .resolved
+            .iter()
+            .map(|(url, record)| ResolvedVars {
+                title: record.title.to_owned(),
+                url: url.to_owned(),
+            })
+            .collect(),
+        text: "".to_owned(),
+    };
+    vars.text = registry.render("section", &vars)?;
+    Ok(vars)
+}
+
+/// Reads a template file without the newline at its end, which editors add.
+fn load(path: &str) -> Result<String> {
+    let contents = read_to_string(path).with_context(|| format!("reading template {}", path))?;
+    Ok(contents
+        .strip_suffix('\n')
+        .map(|s| s.strip_suffix('\r').unwrap_or(s))
+        .unwrap_or(&contents)
+        .to_owned())
+}
+
+fn item_vars(entry: &Entry, now: DateTime<Utc>) -> ItemVars {
+    let item = &entry.item;
+    ItemVars {
+        title: item.title.to_owned(),
+        url: item.url.to_owned(),
+        author: item.author.to_owned(),
+        age: format_age(now.signed_duration_since(item.created_at)),
+        created_at: item.created_at.to_rfc3339(),
+        kind: item.kind.to_string(),
+        group: item.group.to_owned(),
+        labels: item.tags.clone(),
+        reviewers: item.reviewers.clone(),
+        reported: entry.record.as_ref().map(|record| record.times),
+        first_reported: entry
+            .record
+            .as_ref()
+            .map(|record| record.first_reported.format("%Y-%m-%d").to_string()),
+        mentions: entry.mentions.clone(),
+        line: "".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{new_test_item, Failure, Item};
+    use crate::store::Record;
+    use chrono::Duration;
+
+    fn new_report() -> Report {
+        let now = Utc::now();
+        let item = |n: i32, tags: Vec<&str>| Item {
+            title: format!("issue {}", n),
+            tags: tags.into_iter().map(Into::into).collect(),
+            ..new_test_item(
+                &format!("https://github.com/o/r/issues/{}", n),
+                now - Duration::hours(41),
+            )
+        };
+        let mut section = Section::new(
+            "github".to_owned(),
+            "no-reply issues".to_owned(),
+            vec![item(1, vec![]), item(2, vec!["bug", "help wanted"])],
+        );
+        section.entries[1].mentions = vec!["<!channel>".to_owned()];
+        section.resolved = vec![(
+            "https://github.com/o/r/issues/3".to_owned(),
+            Record {
+                source: "github".to_owned(),
+                title: "issue 3".to_owned(),
+                group: "o/r".to_owned(),
+                created_at: Some(now),
+                first_reported: now,
+                last_reported: now,
+                times: 1,
+                channels: vec![],
+                resolved_at: None,
+                expired_at: None,
+            },
+        )];
+        let mut report = Report::new();
+        report.push(section);
+        report
+    }
+
+    fn new_failures() -> Vec<Failure> {
+        vec![
+            Failure {
+                target: "o/x".to_owned(),
+                reason: "not found".to_owned(),
+            },
+            Failure {
+                target: "discourse".to_owned(),
+                reason: "timed out".to_owned(),
+            },
+        ]
+    }
+
+    #[test]
+    fn default_templates() {
+        let templates = Templates::new(&TemplateFiles::default(), None).unwrap();
+        assert_eq!(
+            templates.render(&new_report()).unwrap(),
+            "2 no-reply issues\n\
+             issue 1 https://github.com/o/r/issues/1 opened 41h ago by you06\n\
+             issue 2 https://github.com/o/r/issues/2 opened 41h ago by you06 [bug, help wanted] <!channel>\n\
+             1 resolved since last report\n\
+             issue 3, https://github.com/o/r/issues/3\n"
+        );
+    }
+
+    #[test]
+    fn failures() {
+        let templates = Templates::new(&TemplateFiles::default(), None).unwrap();
+        let mut report = new_report();
+        report.failures = new_failures();
+        let text = templates.render(&report).unwrap();
+        assert!(text.ends_with(
+            "issue 3, https://github.com/o/r/issues/3\n\n\n\
+             could not check: o/x (not found)\n\
+             could not check: discourse (timed out)\n"
+        ));
+        // a report of failures only
+        report.sections.clear();
+        assert_eq!(
+            templates.render(&report).unwrap(),
+            "could not check: o/x (not found)\n\
+             could not check: discourse (timed out)\n"
+        );
+    }
+
+    #[test]
+    fn reviewers() {
+        let templates = Templates::new(&TemplateFiles::default(), None).unwrap();
+        let mut report = new_report();
+        report.sections[0].entries[0].item.reviewers =
+            vec!["you06".to_owned(), "pingcap/tidb-reviewers".to_owned()];
+        assert!(templates.render(&report).unwrap().contains(
+            "issue 1 https://github.com/o/r/issues/1 opened 41h ago by you06 \
+             (review requested from you06, pingcap/tidb-reviewers)\n"
+        ));
+    }
+
+    #[test]
+    fn mrkdwn() {
+        let templates = Templates::new(&TemplateFiles::default(), None).unwrap();
+        let mut report = new_report();
+        report.sections[0].entries[1].item.title = "<!here> & <http://x|y>".to_owned();
+        let text = templates.render_mrkdwn(&report).unwrap();
+        // values are escaped, the mentions of escalation are not
+        assert!(text.contains(
+            "\n&lt;!here&gt; &amp; &lt;http://x|y&gt; https://github.com/o/r/issues/2 \
+             opened 41h ago by you06 [bug, help wanted] <!channel>\n"
+        ));
+        assert!(templates
+            .render(&report)
+            .unwrap()
+            .contains("\n<!here> & <http://x|y> "));
+    }
+
+    #[test]
+    fn item_template() {
+        let files = TemplateFiles::default();
+        let templates = Templates::new(&files, Some("<{{url}}|{{title}}> {{group}}")).unwrap();
+        let text = templates.render(&new_report()).unwrap();
+        assert!(text.contains("\n<https://github.com/o/r/issues/1|issue 1> o/r\n"));
+        assert!(Templates::new(&files, Some("{{#if}}")).is_err());
+    }
+
+    #[test]
+    fn template_files() {
+        // the example files render the same as the defaults
+        let files = TemplateFiles {
+            report: Some("templates/report.hbs".to_owned()),
+            section: Some("templates/section.hbs".to_owned()),
+            item: Some("templates/item.hbs".to_owned()),
+        };
+        let mut report = new_report();
+        let mut section =
+            Section::new("discourse".to_owned(), "no-reply topics".to_owned(), vec![]);
+        section.resolved = new_report().sections.remove(0).resolved;
+        report.push(section);
+        report.failures = new_failures();
+        report.sections[0].entries[0].item.reviewers = vec!["you06".to_owned()];
+        let defaults = Templates::new(&TemplateFiles::default(), None).unwrap();
+        let templates = Templates::new(&files, Some("ignored")).unwrap();
+        assert_eq!(
+            templates.render(&report).unwrap(),
+            defaults.render(&report).unwrap()
+        );
+        assert_eq!(
+            templates.render_mrkdwn(&report).unwrap(),
+            defaults.render_mrkdwn(&report).unwrap()
+        );
+
+        let missing = TemplateFiles {
+            item: Some("templates/missing.hbs".to_owned()),
+            ..Default::default()
+        };
+        assert!(Templates::new(&missing, None).is_err());
+    }
+}